@@ -0,0 +1,109 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use anyhow::Context;
+use ostiarius_core::{wire::Format, Credential, PrivateKey, Signed};
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response},
+    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER},
+    Method, StatusCode, Url,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::time::Duration;
+
+/// HTTP client for the server API, exchanging messages in a given format.
+pub struct Api {
+    client: Client,
+    format: Format,
+    max_retry_wait: Duration,
+}
+
+impl Api {
+    pub fn new(format: Format) -> Self {
+        Api {
+            client: Client::new(),
+            format,
+            max_retry_wait: Duration::ZERO,
+        }
+    }
+
+    /// Retry requests rejected by rate limits, waiting as long as the server
+    /// asks, up to a total of `max_retry_wait`.
+    pub fn with_max_retry_wait(self, max_retry_wait: Duration) -> Self {
+        Api {
+            max_retry_wait,
+            ..self
+        }
+    }
+
+    fn accept(&self, builder: RequestBuilder) -> RequestBuilder {
+        builder.header(ACCEPT, self.format.mime())
+    }
+
+    pub fn get(&self, url: &str) -> reqwest::Result<Response> {
+        self.accept(self.client.get(url)).send()
+    }
+
+    pub fn post<T: Serialize>(&self, url: &str, body: &T) -> anyhow::Result<Response> {
+        let body = self.format.encode(body)?;
+        let mut waited = Duration::ZERO;
+        loop {
+            let res = self
+                .accept(self.client.post(url))
+                .header(CONTENT_TYPE, self.format.mime())
+                .body(body.clone())
+                .send()?;
+            let delay = retry_after(&res).filter(|_| res.status() == StatusCode::TOO_MANY_REQUESTS);
+            match delay {
+                Some(delay) if waited + delay <= self.max_retry_wait => {
+                    eprintln!("Rate limited, retrying in {} seconds", delay.as_secs());
+                    std::thread::sleep(delay);
+                    waited += delay;
+                }
+                _ => return Ok(res),
+            }
+        }
+    }
+
+    /// Send a request without body, with a credential of `name` signed with
+    /// `key`.
+    pub fn send_signed<K: PrivateKey>(
+        &self,
+        method: Method,
+        url: &str,
+        name: &str,
+        key: &K,
+    ) -> anyhow::Result<Response> {
+        let url = Url::parse(url).context("invalid URL")?;
+        let mut credential = Credential::new(name, method.as_str(), url.path());
+        credential.sign(key).context("failed to sign request")?;
+        let res = self
+            .accept(self.client.request(method, url))
+            .header(AUTHORIZATION, credential.to_header()?)
+            .send()?;
+        Ok(res)
+    }
+}
+
+fn retry_after(res: &Response) -> Option<Duration> {
+    res.headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(|delay| Duration::from_secs(delay.max(1)))
+}
+
+/// Decode the body of a response, according to its `Content-Type`.
+pub fn decode<T: DeserializeOwned>(res: Response) -> anyhow::Result<T> {
+    let format = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(Format::from_content_type)
+        .unwrap_or_default();
+    let data = res.bytes().context("failed to read response")?;
+    Ok(format.decode(&data)?)
+}
@@ -0,0 +1,234 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Execution of authorized commands, in the context set by the server.
+
+use anyhow::{bail, Context};
+use ostiarius_core::exec::{ExecContext, Script};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs::File;
+#[cfg(target_os = "linux")]
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Variables of the environment which describe the user running the command.
+type Identity = BTreeMap<String, String>;
+
+/// Run the command as the user and group of the context. The supplementary
+/// groups of the user are set as by `initgroups`, from a list computed before
+/// forking, as looking it up is not safe between fork and exec.
+#[cfg(unix)]
+fn set_credentials(command: &mut Command, context: &ExecContext) -> anyhow::Result<Identity> {
+    use nix::unistd::{getgrouplist, setgid, setgroups, setuid, Gid, Group, Uid, User};
+    use std::ffi::CString;
+    use std::os::unix::process::CommandExt;
+
+    let user = match &context.user {
+        Some(name) => Some(
+            User::from_name(name)
+                .context("failed to look up user")?
+                .with_context(|| format!("unknown user '{}'", name))?,
+        ),
+        None => None,
+    };
+    let gid = match &context.group {
+        Some(name) => Some(
+            Group::from_name(name)
+                .context("failed to look up group")?
+                .with_context(|| format!("unknown group '{}'", name))?
+                .gid,
+        ),
+        None => user.as_ref().map(|user| user.gid),
+    };
+    let uid = user.as_ref().map(|user| user.uid);
+    let switch = matches!(uid, Some(uid) if uid != Uid::effective())
+        || matches!(gid, Some(gid) if gid != Gid::effective());
+    let mut identity = Identity::new();
+    if let Some(user) = &user {
+        let home = user.dir.to_string_lossy().into_owned();
+        identity.insert("HOME".to_string(), home);
+        identity.insert("USER".to_string(), user.name.clone());
+        identity.insert("LOGNAME".to_string(), user.name.clone());
+    }
+    if !switch {
+        return Ok(identity);
+    }
+    if !Uid::effective().is_root() {
+        bail!("switching user or group requires to run as root");
+    }
+    let groups = match (&user, gid) {
+        (Some(user), Some(gid)) => {
+            let name = CString::new(user.name.as_str())?;
+            getgrouplist(&name, gid).context("failed to look up groups of user")?
+        }
+        (None, Some(gid)) => vec![gid],
+        _ => Vec::new(),
+    };
+    // SAFETY: the closure only makes system calls, which are safe to make
+    // between fork and exec.
+    unsafe {
+        command.pre_exec(move || {
+            setgroups(&groups)?;
+            if let Some(gid) = gid {
+                setgid(gid)?;
+            }
+            if let Some(uid) = uid {
+                setuid(uid)?;
+            }
+            Ok(())
+        });
+    }
+    Ok(identity)
+}
+
+#[cfg(not(unix))]
+fn set_credentials(_command: &mut Command, context: &ExecContext) -> anyhow::Result<Identity> {
+    if context.user.is_some() || context.group.is_some() {
+        bail!("switching user or group is not supported on this platform");
+    }
+    Ok(Identity::new())
+}
+
+/// Copy data to a sealed memory file, which can not be modified anymore, and
+/// compute the digest of the copy. The file is closed on exec.
+#[cfg(target_os = "linux")]
+fn sealed_copy<R: Read>(name: &str, mut reader: R) -> anyhow::Result<(File, String)> {
+    use nix::fcntl::{fcntl, FcntlArg, SealFlag};
+    use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+    use std::ffi::CString;
+    use std::io::{Seek, SeekFrom};
+    use std::os::unix::io::AsRawFd;
+
+    let name = CString::new(name)?;
+    let flags = MemFdCreateFlag::MFD_ALLOW_SEALING | MemFdCreateFlag::MFD_CLOEXEC;
+    let mut file = File::from(memfd_create(&name, flags).context("failed to create memory file")?);
+    std::io::copy(&mut reader, &mut file).context("failed to copy to memory file")?;
+    let seals = SealFlag::F_SEAL_SEAL
+        | SealFlag::F_SEAL_SHRINK
+        | SealFlag::F_SEAL_GROW
+        | SealFlag::F_SEAL_WRITE;
+    fcntl(file.as_raw_fd(), FcntlArg::F_ADD_SEALS(seals)).context("failed to seal memory file")?;
+    file.seek(SeekFrom::Start(0))?;
+    let digest = ostiarius_core::exec::digest(&mut file).context("failed to compute digest")?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok((file, digest))
+}
+
+/// Keep a file open in the command, for an interpreter to read it through
+/// `/proc/self/fd`.
+#[cfg(target_os = "linux")]
+fn inherit(file: &File) -> anyhow::Result<()> {
+    use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+    use std::os::unix::io::AsRawFd;
+
+    fcntl(file.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::empty())).context("failed to share file")?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn fd_path(file: &File) -> PathBuf {
+    use std::os::unix::io::AsRawFd;
+
+    PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()))
+}
+
+/// Copy a pinned executable to a sealed memory file and check the digest of
+/// the copy. The command is then run from the copy, through `/proc/self/fd`,
+/// so that the file checked is the one executed even if the original is
+/// rewritten or replaced in between. The copy is only left open in the command
+/// for scripts, whose interpreter reads them from there.
+#[cfg(target_os = "linux")]
+fn open_executable(path: &str, sha256: Option<&str>) -> anyhow::Result<(File, PathBuf)> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+    let (mut copy, digest) = sealed_copy("ostiarius-executable", file)?;
+    if let Some(expected) = sha256 {
+        if digest != expected {
+            bail!("digest of {} does not match the authorization", path);
+        }
+    }
+    let mut magic = [0u8; 2];
+    let is_script = copy.read_exact(&mut magic).is_ok() && magic == *b"#!";
+    if is_script {
+        inherit(&copy)?;
+    }
+    let path = fd_path(&copy);
+    Ok((copy, path))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_executable(_path: &str, _sha256: Option<&str>) -> anyhow::Result<(File, PathBuf)> {
+    bail!("pinned executables are not supported on this platform")
+}
+
+/// Write a script to a sealed memory file, then check the digest of what was
+/// sealed, which is what the interpreter reads through `/proc/self/fd`. The
+/// script is never written to disk.
+#[cfg(target_os = "linux")]
+fn open_script(script: &Script) -> anyhow::Result<(File, PathBuf)> {
+    let (file, digest) = sealed_copy("ostiarius-script", script.body.as_bytes())?;
+    if digest != script.sha256 {
+        bail!("digest of the script does not match the authorization");
+    }
+    eprintln!("Running script {} with {}", digest, script.interpreter);
+    inherit(&file)?;
+    let path = fd_path(&file);
+    Ok((file, path))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_script(_script: &Script) -> anyhow::Result<(File, PathBuf)> {
+    bail!("scripts are not supported on this platform")
+}
+
+/// Execute a command in the given context. Fails without running the command
+/// if the context can not be applied.
+pub fn execute(command: &str, context: &ExecContext) -> anyhow::Result<bool> {
+    execute_argv(&ostiarius_core::matcher::argv(command), context)
+}
+
+/// Execute exactly the given arguments, program first, in the given context.
+/// With a script, the interpreter runs it with the arguments after the program.
+pub fn execute_argv<S: AsRef<OsStr>>(args: &[S], context: &ExecContext) -> anyhow::Result<bool> {
+    let (program, args) = match args.split_first() {
+        Some(argv) => argv,
+        None => bail!("empty command"),
+    };
+    // The opened file must outlive the command.
+    let (_file, mut command) = match (&context.executable, &context.script) {
+        (Some(executable), _) => {
+            let (file, path) = open_executable(executable, context.sha256.as_deref())?;
+            let mut command = Command::new(path);
+            #[cfg(unix)]
+            std::os::unix::process::CommandExt::arg0(&mut command, program);
+            (Some(file), command)
+        }
+        (None, Some(script)) => {
+            let (file, path) = open_script(script)?;
+            let mut command = Command::new(&script.interpreter);
+            command.arg(path);
+            (Some(file), command)
+        }
+        (None, None) => (None, Command::new(program)),
+    };
+    command.args(args);
+    let identity = set_credentials(&mut command, context)?;
+    if let Some(cwd) = &context.cwd {
+        command.current_dir(cwd);
+    }
+    if !context.env.is_empty() || !identity.is_empty() {
+        let mut env = context.env.apply(std::env::vars());
+        for (name, value) in identity {
+            if !context.env.set.contains_key(&name) {
+                env.insert(name, value);
+            }
+        }
+        command.env_clear().envs(env);
+    }
+    let status = command.status().context("failed to execute command")?;
+    Ok(status.success())
+}
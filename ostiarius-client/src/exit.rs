@@ -0,0 +1,68 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Exit codes of the client, one per class of failure.
+
+use ostiarius_core::problem::{Problem, Reason, PROBLEM_JSON};
+use reqwest::{blocking::Response, header::CONTENT_TYPE};
+
+pub const FORBIDDEN: i32 = 2;
+pub const MISMATCH: i32 = 3;
+pub const COMMAND_FAILED: i32 = 4;
+pub const TIMED_OUT: i32 = 5;
+pub const UNKNOWN_CLIENT: i32 = 6;
+pub const OUTSIDE_TIME_WINDOW: i32 = 7;
+pub const RATE_LIMITED: i32 = 8;
+pub const BAD_REQUEST: i32 = 9;
+pub const SERVER_ERROR: i32 = 10;
+pub const APPROVAL_DENIED: i32 = 11;
+
+pub fn code(reason: Reason) -> i32 {
+    match reason {
+        Reason::Forbidden | Reason::CommandNotAllowed => FORBIDDEN,
+        Reason::ApprovalDenied => APPROVAL_DENIED,
+        Reason::UnknownClient => UNKNOWN_CLIENT,
+        Reason::OutsideTimeWindow => OUTSIDE_TIME_WINDOW,
+        Reason::RateLimited => RATE_LIMITED,
+        Reason::InvalidParameter | Reason::BadChallenge => BAD_REQUEST,
+        Reason::InternalError => SERVER_ERROR,
+    }
+}
+
+/// Report why the server rejected a request, then exit.
+pub fn rejected(res: Response, message: &str) -> ! {
+    let status = res.status();
+    let is_problem = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.starts_with(PROBLEM_JSON))
+        .unwrap_or(false);
+    let problem = if is_problem {
+        res.bytes()
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Problem>(&data).ok())
+    } else {
+        None
+    };
+    match problem {
+        Some(problem) => {
+            let detail = problem.detail.unwrap_or(problem.title);
+            eprintln!("{}: {}", message, detail);
+            std::process::exit(code(problem.reason))
+        }
+        None => {
+            eprintln!("{}: {}", message, status);
+            std::process::exit(match status.as_u16() {
+                401 | 403 => FORBIDDEN,
+                429 => RATE_LIMITED,
+                _ if status.is_server_error() => SERVER_ERROR,
+                _ if status.is_client_error() => BAD_REQUEST,
+                _ => FORBIDDEN,
+            })
+        }
+    }
+}
@@ -4,14 +4,50 @@
 // SPDX-License-Identifier: MIT
 //
 
-use anyhow::Context;
+mod api;
+mod exec;
+mod exit;
+mod offline;
+mod session;
+mod state;
+
+use anyhow::{bail, Context};
+use api::{decode, Api};
+use chrono::{DateTime, Utc};
+use exec::{execute, execute_argv};
 use gumdrop::Options;
+use offline::{pending_path, PendingRequest};
+use openssl::{pkey::Public, rsa::Rsa};
+#[cfg(unix)]
+use ostiarius_core::serial::{self, SerialAddress};
 use ostiarius_core::{
-    crypto::password::PasswordProvider, utils::insert_password, Error, Requester,
+    action::Params,
+    crypto::{fingerprint, normalize_fingerprint, password::PasswordProvider},
+    exec::ExecContext,
+    known_servers::normalize_url,
+    utils::insert_password,
+    wire::Format,
+    Approval, Authorization, BatchAuthorization, Error, ExplainRequest, Explanation, KnownServers,
+    OfflineAuthorization, OfflineRequest, PendingAuthorization, Request, Requester, RsaPrivateKey,
+    ServerInfo, Session, SessionStatus, Signed,
 };
-use reqwest::blocking;
+use reqwest::{Method, StatusCode};
+use session::{session_path, StoredSession};
+use std::io::{Read, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+/// Time the server is asked to wait for a decision about a pending request.
+const APPROVAL_POLL_INTERVAL: u64 = 20;
+
+/// Maximum time to wait before retrying requests rejected by rate limits.
+const DEFAULT_MAX_RETRY_WAIT: u64 = 60;
+
+#[cfg(unix)]
+const SERIAL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(25);
+
 #[derive(Debug, Options)]
 struct ClientOptions {
     #[options(help = "Print this help message and exit")]
@@ -22,8 +58,18 @@ struct ClientOptions {
     name: Option<String>,
     #[options(help = "URI of server private key", meta = "URI")]
     priv_key: Option<String>,
-    #[options(help = "Path to server public key")]
+    #[options(help = "Path to server public key, instead of known servers")]
     server_pub_key: Option<String>,
+    #[options(help = "Path to known servers file", meta = "FILE")]
+    known_servers: Option<String>,
+    #[options(help = "Encode messages exchanged with server in CBOR")]
+    cbor: bool,
+    #[options(
+        help = "Maximum time to wait when rate limited by server (default: 60)",
+        meta = "SECONDS",
+        no_short
+    )]
+    max_retry_wait: Option<u64>,
     #[options(
         help = "Password provider",
         meta = "PROVIDER",
@@ -31,81 +77,950 @@ struct ClientOptions {
         short = "S"
     )]
     password_provider: Option<String>,
+    #[options(command)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Options)]
+enum Command {
+    #[options(help = "Request authorization and execute a command")]
+    Run(RunOptions),
+    #[options(help = "Request authorization and execute an action of the policy")]
+    Action(ActionOptions),
+    #[options(help = "Request authorization and execute commands listed in a file")]
+    Batch(BatchOptions),
+    #[options(help = "Open a session to execute commands without further authorization")]
+    OpenSession(OpenSessionOptions),
+    #[options(help = "Close the session opened with a server")]
+    CloseSession(CloseSessionOptions),
+    #[options(help = "List the active sessions, as an operator")]
+    Sessions(SessionsOptions),
+    #[options(help = "Revoke a session, as an operator")]
+    RevokeSession(RevokeSessionOptions),
+    #[options(help = "Write an authorization request to a file, for offline exchange")]
+    Request(RequestOptions),
+    #[options(help = "Execute a command authorized offline")]
+    Redeem(RedeemOptions),
+    #[options(help = "Enroll the public key of a server")]
+    Trust(TrustOptions),
+    #[options(help = "List requests waiting for the approval of an operator, as an operator")]
+    Pending(PendingOptions),
+    #[options(help = "Approve a pending request, as an operator")]
+    Approve(ApproveOptions),
+    #[options(help = "Deny a pending request, as an operator")]
+    Deny(ApproveOptions),
+    #[options(help = "Explain the decision about a command of a client, as an operator")]
+    Explain(ExplainOptions),
+}
+
+#[derive(Debug, Options)]
+struct RunOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(
+        help = "Interval between session revocation checks (default: 60)",
+        meta = "SECONDS"
+    )]
+    revocation_interval: Option<u32>,
+    #[options(
+        help = "Maximum time to wait for the approval of an operator (default: 600)",
+        meta = "SECONDS"
+    )]
+    approval_timeout: Option<u64>,
     #[options(free)]
     url: String,
     #[options(free)]
     command: String,
 }
 
-fn main() -> anyhow::Result<()> {
-    let options = ClientOptions::parse_args_default_or_exit();
-    if options.version {
-        println!("ostiarius-client {}", env!("CARGO_PKG_VERSION"));
-        std::process::exit(0);
+#[derive(Debug, Options)]
+struct ActionOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(
+        help = "Maximum time to wait for the approval of an operator (default: 600)",
+        meta = "SECONDS"
+    )]
+    approval_timeout: Option<u64>,
+    #[options(free)]
+    url: String,
+    #[options(free)]
+    action: String,
+    #[options(free)]
+    params: Vec<String>,
+}
+
+#[derive(Debug, Options)]
+struct BatchOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(free)]
+    url: String,
+    #[options(free)]
+    file: String,
+}
+
+#[derive(Debug, Options)]
+struct OpenSessionOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(help = "Requested duration of the session", meta = "MINUTES")]
+    duration: Option<u32>,
+    #[options(free)]
+    url: String,
+}
+
+#[derive(Debug, Options)]
+struct CloseSessionOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(free)]
+    url: String,
+}
+
+#[derive(Debug, Options)]
+struct SessionsOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(free, help = "URL of the server")]
+    url: String,
+}
+
+#[derive(Debug, Options)]
+struct RevokeSessionOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(free, help = "URL of the server")]
+    url: String,
+    #[options(free, help = "Identifier of the session to revoke")]
+    id: Option<Uuid>,
+}
+
+#[derive(Debug, Options)]
+struct RequestOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(help = "Path to output file (default: standard output)", meta = "FILE")]
+    out: Option<String>,
+    #[options(free)]
+    command: String,
+}
+
+#[derive(Debug, Options)]
+struct RedeemOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(free)]
+    file: String,
+}
+
+#[derive(Debug, Options)]
+struct TrustOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(
+        help = "Expected fingerprint of server public key",
+        meta = "FINGERPRINT"
+    )]
+    fingerprint: Option<String>,
+    #[options(help = "Store key in system-wide known servers file")]
+    system: bool,
+    #[options(help = "Replace key if server is already known", no_short)]
+    force: bool,
+    #[options(free)]
+    url: String,
+}
+
+#[derive(Debug, Options)]
+struct PendingOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(free)]
+    url: String,
+}
+
+#[derive(Debug, Options)]
+struct ApproveOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(free)]
+    url: String,
+    #[options(free)]
+    id: Option<Uuid>,
+}
+
+#[derive(Debug, Options)]
+struct ExplainOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(help = "Name of the client", meta = "NAME")]
+    client: String,
+    #[options(help = "Address the request comes from", meta = "ADDRESS")]
+    source: Option<IpAddr>,
+    #[options(
+        help = "Time of the request, in RFC 3339 format (default: now)",
+        meta = "TIME"
+    )]
+    at: Option<DateTime<Utc>>,
+    #[options(free)]
+    url: String,
+    #[options(free)]
+    command: Vec<String>,
+}
+
+fn user_known_servers() -> Option<PathBuf> {
+    #[cfg(windows)]
+    let dir = std::env::var_os("APPDATA").map(PathBuf::from);
+    #[cfg(not(windows))]
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    dir.map(|dir| dir.join("ostiarius").join("known_servers"))
+}
+
+fn system_known_servers() -> PathBuf {
+    #[cfg(windows)]
+    let dir = std::env::var_os("PROGRAMDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("C:\\ProgramData"));
+    #[cfg(not(windows))]
+    let dir = PathBuf::from("/etc");
+    dir.join("ostiarius").join("known_servers")
+}
+
+fn known_servers_paths(options: &ClientOptions) -> Vec<PathBuf> {
+    match &options.known_servers {
+        Some(path) => vec![PathBuf::from(path)],
+        None => user_known_servers()
+            .into_iter()
+            .chain(std::iter::once(system_known_servers()))
+            .collect(),
     }
-    let name = match options.name {
-        Some(name) => name,
+}
+
+fn read_pub_key(path: &str) -> anyhow::Result<Rsa<Public>> {
+    let pem = std::fs::read(path).context("failed to read server public key")?;
+    Rsa::public_key_from_pem(&pem).context("failed to parse server public key")
+}
+
+fn server_pub_key(options: &ClientOptions, url: &str) -> anyhow::Result<Rsa<Public>> {
+    if let Some(path) = &options.server_pub_key {
+        return read_pub_key(path);
+    }
+    for path in known_servers_paths(options) {
+        let known_servers = KnownServers::from_file(&path)
+            .with_context(|| format!("failed to load {}", path.display()))?;
+        if let Some(server) = known_servers.find(url) {
+            return Ok(server.pub_key.clone());
+        }
+    }
+    bail!(
+        "unknown server {}, use 'ostiarius-client trust {}' to enroll it",
+        url,
+        url
+    )
+}
+
+fn client_name(options: &ClientOptions) -> anyhow::Result<String> {
+    let name = match &options.name {
+        Some(name) => name.clone(),
         None => hostname::get()
             .context("failed to get hostname")?
             .into_string()
             .map_err(Error::InvalidPath)
             .context("failed to convert hostname")?,
     };
-    let server_pub_key = options
+    Ok(name)
+}
+
+fn offline_server_pub_key(options: &ClientOptions) -> anyhow::Result<Rsa<Public>> {
+    let path = options
         .server_pub_key
-        .unwrap_or_else(|| "server.pubkey.pem".to_string());
+        .as_ref()
+        .context("missing server public key, use --server-pub-key")?;
+    read_pub_key(path)
+}
+
+fn priv_key_uri(options: &ClientOptions) -> anyhow::Result<String> {
     let mut path = std::env::current_dir().context("failed to get current directory")?;
     path.push("server.privkey.pem");
-    let client_priv_key = options
+    let priv_key = options
         .priv_key
+        .clone()
         .unwrap_or(format!("file://{}", path.display()));
-    let password_provider = match options.password_provider {
+    let password_provider = match &options.password_provider {
         Some(provider) => provider.parse()?,
         None => PasswordProvider::Prompt,
     };
     let password = password_provider
         .provide()
         .context("failed to get password")?;
-    let client_priv_key = insert_password(&password, &client_priv_key)?;
-    let requester =
-        Requester::new(&client_priv_key, server_pub_key).context("failed to create requester")?;
+    Ok(insert_password(&password, &priv_key)?)
+}
+
+fn create_requester(
+    options: &ClientOptions,
+    server_pub_key: Rsa<Public>,
+) -> anyhow::Result<Requester> {
+    let client_priv_key = priv_key_uri(options)?;
+    Requester::with_pub_key(&client_priv_key, server_pub_key).context("failed to create requester")
+}
+
+fn api(options: &ClientOptions) -> Api {
+    let format = if options.cbor {
+        Format::Cbor
+    } else {
+        Format::Json
+    };
+    let max_retry_wait = options.max_retry_wait.unwrap_or(DEFAULT_MAX_RETRY_WAIT);
+    Api::new(format).with_max_retry_wait(Duration::from_secs(max_retry_wait))
+}
+
+fn session_allows(
+    options: &ClientOptions,
+    run_options: &RunOptions,
+    url: &str,
+    name: &str,
+) -> anyhow::Result<bool> {
+    let path = session_path(url)?;
+    let mut stored = match StoredSession::load(&path)? {
+        Some(stored) => stored,
+        None => return Ok(false),
+    };
+    let now = chrono::offset::Utc::now();
+    let session = &stored.session;
+    if stored.url != url || session.is_expired(now) {
+        StoredSession::remove(&path)?;
+        return Ok(false);
+    }
+    if session.name != name || !session.allows(&run_options.command) {
+        return Ok(false);
+    }
+    let server_pub_key = server_pub_key(options, url)?;
+    if !session.verify(&server_pub_key)? {
+        eprintln!("Invalid session signature, discarding session");
+        StoredSession::remove(&path)?;
+        return Ok(false);
+    }
+    let interval = run_options.revocation_interval.unwrap_or(60);
+    if now - stored.checked >= chrono::Duration::seconds(interval.into()) {
+        // Only a status signed by the server for this nonce proves the session
+        // is still active, anything else counts as a revocation.
+        let nonce = Uuid::new_v4();
+        let res = api(options)
+            .get(&format!(
+                "{}/api/v1/sessions/{}?nonce={}",
+                url, session.id, nonce
+            ))
+            .context("failed to check session")?;
+        let active = match res.error_for_status().map(decode::<SessionStatus>) {
+            Ok(Ok(status)) => {
+                status.verify(&server_pub_key).unwrap_or(false)
+                    && status.confirms(session.id, nonce)
+            }
+            _ => false,
+        };
+        if !active {
+            eprintln!("Session revoked");
+            StoredSession::remove(&path)?;
+            return Ok(false);
+        }
+        stored.checked = now;
+        stored.save(&path)?;
+    }
+    Ok(true)
+}
+
+fn request_http(
+    api: &Api,
+    url: &str,
+    request: &Request,
+    approval_timeout: Duration,
+) -> anyhow::Result<Authorization> {
+    let url = format!("{}/api/v1/authorizations", url);
+    let res = api
+        .post(&url, request)
+        .context("failed to post authorization request")?;
+    if !res.status().is_success() {
+        exit::rejected(res, "Forbidden to execute command");
+    }
+    let pending = res.status() == StatusCode::ACCEPTED;
+    let uuid = decode::<Uuid>(res).context("failed to decode response")?;
+    if pending {
+        eprintln!("Waiting for approval of request {}", uuid);
+    }
+    let deadline = Instant::now() + approval_timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now()).as_secs();
+        let wait = if pending {
+            remaining.min(APPROVAL_POLL_INTERVAL)
+        } else {
+            0
+        };
+        let res = api
+            .get(&format!("{}/{}?wait={}", &url, &uuid, wait))
+            .context("failed to get authorization")?;
+        match res.status() {
+            StatusCode::OK => return decode(res).context("failed to decode authorization"),
+            StatusCode::ACCEPTED if remaining > 0 => continue,
+            StatusCode::ACCEPTED => {
+                eprintln!("Timed out waiting for approval");
+                std::process::exit(exit::TIMED_OUT);
+            }
+            _ => exit::rejected(res, "Forbidden to execute command"),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn request_serial(url: &str, request: &Request) -> anyhow::Result<Authorization> {
+    let address = url.parse::<SerialAddress>()?;
+    let mut port = serial::open(&address.path, address.baud_rate, Some(SERIAL_TIMEOUT))
+        .with_context(|| format!("failed to open {}", address.path.display()))?;
+    match serial::exchange(&mut port, request) {
+        Err(Error::Unauthorized) => {
+            eprintln!("Forbidden to execute command");
+            std::process::exit(exit::FORBIDDEN);
+        }
+        result => result.context("failed to request authorization over serial line"),
+    }
+}
+
+#[cfg(not(unix))]
+fn request_serial(_url: &str, _request: &Request) -> anyhow::Result<Authorization> {
+    bail!("serial transport is not supported on this platform")
+}
+
+fn request_authorization(
+    options: &ClientOptions,
+    base_url: &str,
+    request: &Request,
+    approval_timeout: Option<u64>,
+) -> anyhow::Result<Authorization> {
+    if base_url.starts_with("serial:") {
+        request_serial(base_url, request)
+    } else {
+        let timeout = Duration::from_secs(approval_timeout.unwrap_or(600));
+        request_http(&api(options), base_url, request, timeout)
+    }
+}
+
+fn run(options: &ClientOptions, run_options: &RunOptions) -> anyhow::Result<()> {
+    let name = client_name(options)?;
+    let base_url = normalize_url(&run_options.url);
+    if session_allows(options, run_options, base_url, &name)? {
+        // Sessions never cover commands with a context of execution.
+        if !execute(&run_options.command, &ExecContext::default())? {
+            eprintln!("Command failed");
+            std::process::exit(exit::COMMAND_FAILED);
+        }
+        return Ok(());
+    }
+    let requester = create_requester(options, server_pub_key(options, base_url)?)?;
     let request = requester
-        .make(&name, &options.command)
+        .make(&name, &run_options.command)
         .context("failed to make request")?;
-    let client = blocking::Client::new();
-    let url = format!("{}/api/v1/authorizations", options.url);
-    let res = client
-        .post(&url)
-        .json(&request)
-        .send()
-        .context("failed to post authorization request")?;
+    let authorization =
+        request_authorization(options, base_url, &request, run_options.approval_timeout)?;
+    let approved = requester
+        .check(&authorization)
+        .context("failed to check authorization")?;
+    if !approved || authorization.command != run_options.command {
+        eprintln!("Authorization mismatch");
+        std::process::exit(exit::MISMATCH);
+    }
+    if !execute(&run_options.command, &authorization.exec)? {
+        eprintln!("Command failed");
+        std::process::exit(exit::COMMAND_FAILED);
+    }
+    Ok(())
+}
+
+fn parse_params(params: &[String]) -> anyhow::Result<Params> {
+    params
+        .iter()
+        .map(|param| match param.split_once('=') {
+            Some((name, value)) => Ok((name.to_string(), value.to_string())),
+            None => bail!("invalid parameter '{}', expected NAME=VALUE", param),
+        })
+        .collect()
+}
+
+fn action(options: &ClientOptions, action_options: &ActionOptions) -> anyhow::Result<()> {
+    let name = client_name(options)?;
+    let base_url = normalize_url(&action_options.url);
+    let params = parse_params(&action_options.params)?;
+    let requester = create_requester(options, server_pub_key(options, base_url)?)?;
+    let request = requester
+        .make_action(&name, &action_options.action, params)
+        .context("failed to make request")?;
+    let authorization =
+        request_authorization(options, base_url, &request, action_options.approval_timeout)?;
+    let approved = requester
+        .check(&authorization)
+        .context("failed to check authorization")?;
+    if !approved
+        || authorization.command != action_options.action
+        || authorization.params != request.params
+        || authorization.argv.is_empty()
+    {
+        eprintln!("Authorization mismatch");
+        std::process::exit(exit::MISMATCH);
+    }
+    if !execute_argv(&authorization.argv, &authorization.exec)? {
+        eprintln!("Command failed");
+        std::process::exit(exit::COMMAND_FAILED);
+    }
+    Ok(())
+}
+
+fn read_commands(path: &str) -> anyhow::Result<Vec<String>> {
+    let contents = if path == "-" {
+        let mut contents = String::new();
+        std::io::stdin()
+            .read_to_string(&mut contents)
+            .context("failed to read commands")?;
+        contents
+    } else {
+        std::fs::read_to_string(path).context("failed to read commands")?
+    };
+    let commands = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect();
+    Ok(commands)
+}
+
+fn batch(options: &ClientOptions, batch_options: &BatchOptions) -> anyhow::Result<()> {
+    let name = client_name(options)?;
+    let base_url = normalize_url(&batch_options.url);
+    let commands = read_commands(&batch_options.file)?;
+    if commands.is_empty() {
+        bail!("no command to execute");
+    }
+    let requester = create_requester(options, server_pub_key(options, base_url)?)?;
+    let request = requester
+        .make_batch(&name, &commands)
+        .context("failed to make request")?;
+    let res = api(options)
+        .post(&format!("{}/api/v1/batches", base_url), &request)
+        .context("failed to post batch authorization request")?;
     if !res.status().is_success() {
-        eprintln!("Forbidden to execute command");
-        std::process::exit(2);
+        exit::rejected(res, "Forbidden to execute commands");
     }
-    let uuid = res.json::<Uuid>().context("failed to decode response")?;
-    let authorization = client
-        .get(format!("{}/{}", &url, &uuid))
-        .send()
-        .context("failed to get authorization")?;
+    let batch = decode::<BatchAuthorization>(res).context("failed to decode response")?;
     let approved = requester
-        .check(&authorization.json()?)
+        .check_batch(&batch)
         .context("failed to check authorization")?;
-    if !approved {
+    let decided = batch.decisions.iter().map(|decision| &decision.command);
+    if !approved || batch.name != name || !decided.eq(commands.iter()) {
         eprintln!("Authorization mismatch");
-        std::process::exit(3);
-    }
-    let args = options
-        .command
-        .split_ascii_whitespace()
-        .collect::<Vec<&str>>();
-    let status = std::process::Command::new(args[0])
-        .args(args[1..].iter())
-        .status()
-        .context("failed to execute command")?;
-    if !status.success() {
+        std::process::exit(exit::MISMATCH);
+    }
+    for (command, decision) in commands.iter().zip(batch.decisions.iter()) {
+        if !decision.authorized {
+            eprintln!("Forbidden to execute command: {}", command);
+            std::process::exit(exit::FORBIDDEN);
+        }
+        if !execute(command, &decision.exec)? {
+            eprintln!("Command failed: {}", command);
+            std::process::exit(exit::COMMAND_FAILED);
+        }
+    }
+    Ok(())
+}
+
+fn open_session(
+    options: &ClientOptions,
+    session_options: &OpenSessionOptions,
+) -> anyhow::Result<()> {
+    let name = client_name(options)?;
+    let base_url = normalize_url(&session_options.url);
+    let requester = create_requester(options, server_pub_key(options, base_url)?)?;
+    let request = requester
+        .make_session(&name, session_options.duration)
+        .context("failed to make request")?;
+    let res = api(options)
+        .post(&format!("{}/api/v1/sessions", base_url), &request)
+        .context("failed to post session request")?;
+    if !res.status().is_success() {
+        exit::rejected(res, "Forbidden to open session");
+    }
+    let session = decode::<Session>(res).context("failed to decode response")?;
+    let approved = requester
+        .check_session(&session)
+        .context("failed to check session")?;
+    if !approved || session.name != name {
+        eprintln!("Authorization mismatch");
+        std::process::exit(exit::MISMATCH);
+    }
+    let stored = StoredSession {
+        url: base_url.to_string(),
+        checked: chrono::offset::Utc::now(),
+        session,
+    };
+    stored.save(&session_path(base_url)?)?;
+    println!(
+        "Opened session {} until {}",
+        stored.session.id, stored.session.expires
+    );
+    Ok(())
+}
+
+fn close_session(
+    options: &ClientOptions,
+    session_options: &CloseSessionOptions,
+) -> anyhow::Result<()> {
+    let base_url = normalize_url(&session_options.url);
+    let path = session_path(base_url)?;
+    let stored = StoredSession::load(&path)?.context("no session opened")?;
+    let priv_key = RsaPrivateKey::from_uri(&priv_key_uri(options)?)
+        .context("failed to load client private key")?;
+    let res = api(options).send_signed(
+        Method::DELETE,
+        &format!("{}/api/v1/sessions/{}", base_url, stored.session.id),
+        &stored.session.name,
+        &priv_key,
+    )?;
+    if !res.status().is_success() && res.status() != StatusCode::NOT_FOUND {
+        bail!("failed to revoke session: {}", res.status());
+    }
+    StoredSession::remove(&path)?;
+    println!("Closed session {}", stored.session.id);
+    Ok(())
+}
+
+fn sessions(options: &ClientOptions, sessions_options: &SessionsOptions) -> anyhow::Result<()> {
+    let url = normalize_url(&sessions_options.url);
+    let operator = client_name(options)?;
+    let priv_key = RsaPrivateKey::from_uri(&priv_key_uri(options)?)
+        .context("failed to load operator private key")?;
+    let res = api(options).send_signed(
+        Method::GET,
+        &format!("{}/api/v1/sessions", url),
+        &operator,
+        &priv_key,
+    )?;
+    if res.status() == StatusCode::FORBIDDEN {
+        bail!("operator '{}' not allowed to list sessions", operator);
+    }
+    let res = res.error_for_status().context("failed to get sessions")?;
+    let sessions = decode::<Vec<Session>>(res).context("failed to decode response")?;
+    for session in sessions {
+        println!(
+            "{} '{}' until {}: {}",
+            session.id,
+            session.name,
+            session.expires,
+            session.commands.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn revoke_session(
+    options: &ClientOptions,
+    revoke_options: &RevokeSessionOptions,
+) -> anyhow::Result<()> {
+    let url = normalize_url(&revoke_options.url);
+    let id = revoke_options.id.context("missing session identifier")?;
+    let operator = client_name(options)?;
+    let priv_key = RsaPrivateKey::from_uri(&priv_key_uri(options)?)
+        .context("failed to load operator private key")?;
+    let res = api(options).send_signed(
+        Method::DELETE,
+        &format!("{}/api/v1/sessions/{}", url, id),
+        &operator,
+        &priv_key,
+    )?;
+    match res.status() {
+        StatusCode::FORBIDDEN => bail!("operator '{}' not allowed to revoke sessions", operator),
+        StatusCode::NOT_FOUND => bail!("no active session {}", id),
+        status if !status.is_success() => bail!("failed to revoke session: {}", status),
+        _ => println!("Revoked session {}", id),
+    }
+    Ok(())
+}
+
+fn request(options: &ClientOptions, request_options: &RequestOptions) -> anyhow::Result<()> {
+    let name = client_name(options)?;
+    let requester = create_requester(options, offline_server_pub_key(options)?)?;
+    let request = requester
+        .make(&name, &request_options.command)
+        .context("failed to make request")?;
+    let request = OfflineRequest::new(request);
+    let pending = PendingRequest::new(&name, &request_options.command, requester.token());
+    pending.save(&pending_path(&request.id)?)?;
+    let armored = request.to_armor().context("failed to encode request")?;
+    match &request_options.out {
+        Some(path) if path != "-" => {
+            std::fs::write(path, armored).context("failed to write request")?;
+            eprintln!("Wrote request {} to {}", request.id, path);
+        }
+        _ => print!("{}", armored),
+    }
+    Ok(())
+}
+
+fn redeem(options: &ClientOptions, redeem_options: &RedeemOptions) -> anyhow::Result<()> {
+    let armored =
+        std::fs::read_to_string(&redeem_options.file).context("failed to read authorization")?;
+    let offline =
+        OfflineAuthorization::from_armor(&armored).context("failed to decode authorization")?;
+    let path = pending_path(&offline.request_id)?;
+    let pending = PendingRequest::load(&path)?.context("no such pending request")?;
+    let requester =
+        create_requester(options, offline_server_pub_key(options)?)?.with_token(pending.token()?);
+    let authorization = &offline.authorization;
+    let approved = requester
+        .check(authorization)
+        .context("failed to check authorization")?;
+    if !approved || authorization.name != pending.name || authorization.command != pending.command {
+        eprintln!("Authorization mismatch");
+        std::process::exit(exit::MISMATCH);
+    }
+    PendingRequest::remove(&path)?;
+    if !execute(&authorization.command, &authorization.exec)? {
         eprintln!("Command failed");
-        std::process::exit(4);
+        std::process::exit(exit::COMMAND_FAILED);
+    }
+    Ok(())
+}
+
+fn confirm(url: &str, fingerprint: &str) -> anyhow::Result<bool> {
+    println!("The fingerprint of the public key of {} is:", url);
+    println!("{}", fingerprint);
+    print!("Do you want to trust this server (yes/no)? ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .context("failed to read answer")?;
+    Ok(matches!(answer.trim(), "yes" | "y"))
+}
+
+fn trust(options: &ClientOptions, trust_options: &TrustOptions) -> anyhow::Result<()> {
+    let url = normalize_url(&trust_options.url);
+    let path = if trust_options.system {
+        system_known_servers()
+    } else {
+        match &options.known_servers {
+            Some(path) => PathBuf::from(path),
+            None => user_known_servers().context("failed to locate known servers file")?,
+        }
+    };
+    let res = api(options)
+        .get(&format!("{}/api/v1/info", url))
+        .and_then(|res| res.error_for_status())
+        .context("failed to get server info")?;
+    let info = decode::<ServerInfo>(res).context("failed to decode server info")?;
+    let pub_key = info
+        .pub_key()
+        .context("failed to parse server public key")?;
+    let fingerprint = fingerprint(&pub_key)?;
+    let mut known_servers = KnownServers::from_file(&path)
+        .with_context(|| format!("failed to load {}", path.display()))?;
+    if let Some(server) = known_servers.find(url) {
+        let known = server.fingerprint()?;
+        if known == fingerprint {
+            println!("Server {} is already trusted", url);
+            return Ok(());
+        }
+        if !trust_options.force {
+            bail!(
+                "public key of server {} has changed (known: {}, offered: {}), use --force to replace it",
+                url,
+                known,
+                fingerprint
+            );
+        }
+    }
+    match &trust_options.fingerprint {
+        Some(expected) if normalize_fingerprint(expected) != fingerprint => {
+            bail!(
+                "fingerprint mismatch for server {} (expected: {}, offered: {})",
+                url,
+                expected,
+                fingerprint
+            );
+        }
+        Some(_) => {}
+        None => {
+            if !confirm(url, &fingerprint)? {
+                bail!("server {} not trusted", url);
+            }
+        }
     }
+    known_servers.insert(url, pub_key);
+    known_servers
+        .save(&path)
+        .with_context(|| format!("failed to save {}", path.display()))?;
+    println!("Added server {} to {}", url, path.display());
     Ok(())
 }
+
+fn pending(options: &ClientOptions, pending_options: &PendingOptions) -> anyhow::Result<()> {
+    let url = normalize_url(&pending_options.url);
+    let operator = client_name(options)?;
+    let priv_key = RsaPrivateKey::from_uri(&priv_key_uri(options)?)
+        .context("failed to load operator private key")?;
+    let res = api(options).send_signed(
+        Method::GET,
+        &format!("{}/api/v1/approvals", url),
+        &operator,
+        &priv_key,
+    )?;
+    if res.status() == StatusCode::FORBIDDEN {
+        bail!(
+            "operator '{}' not allowed to list pending requests",
+            operator
+        );
+    }
+    let res = res
+        .error_for_status()
+        .context("failed to get pending requests")?;
+    let pending = decode::<Vec<PendingAuthorization>>(res).context("failed to decode response")?;
+    for request in pending {
+        println!(
+            "{} {} '{}' {} ({}/{} approvals)",
+            request.id,
+            request.timestamp,
+            request.name,
+            request.command,
+            request.approvals.len(),
+            request.required
+        );
+    }
+    Ok(())
+}
+
+fn decide(
+    options: &ClientOptions,
+    approve_options: &ApproveOptions,
+    approved: bool,
+) -> anyhow::Result<()> {
+    let url = normalize_url(&approve_options.url);
+    let id = approve_options.id.context("missing request identifier")?;
+    let operator = client_name(options)?;
+    let priv_key = RsaPrivateKey::from_uri(&priv_key_uri(options)?)
+        .context("failed to load operator private key")?;
+    let mut approval = Approval::new(id, &operator, approved);
+    approval
+        .sign(&priv_key)
+        .context("failed to sign approval")?;
+    let res = api(options)
+        .post(&format!("{}/api/v1/approvals", url), &approval)
+        .context("failed to post approval")?;
+    match res.status() {
+        StatusCode::FORBIDDEN => bail!("operator '{}' not allowed to approve requests", operator),
+        StatusCode::NOT_FOUND => bail!("no pending request {}", id),
+        StatusCode::CONFLICT => bail!(
+            "request {} already denied or approved by '{}'",
+            id,
+            operator
+        ),
+        StatusCode::ACCEPTED => println!("Approved request {}, waiting for more approvals", id),
+        status if !status.is_success() => bail!("failed to post approval: {}", status),
+        _ => println!(
+            "{} request {}",
+            if approved { "Approved" } else { "Denied" },
+            id
+        ),
+    }
+    Ok(())
+}
+
+fn explain(options: &ClientOptions, explain_options: &ExplainOptions) -> anyhow::Result<()> {
+    let url = normalize_url(&explain_options.url);
+    if explain_options.command.is_empty() {
+        bail!("missing command to explain");
+    }
+    let operator = client_name(options)?;
+    let priv_key = RsaPrivateKey::from_uri(&priv_key_uri(options)?)
+        .context("failed to load operator private key")?;
+    let command = explain_options.command.join(" ");
+    let mut request = ExplainRequest::new(&operator, &explain_options.client, &command);
+    request.source = explain_options.source;
+    request.time = explain_options.at;
+    request
+        .sign(&priv_key)
+        .context("failed to sign explain request")?;
+    let res = api(options)
+        .post(&format!("{}/api/v1/policy/explain", url), &request)
+        .context("failed to post explain request")?;
+    if res.status() == StatusCode::FORBIDDEN {
+        bail!("operator '{}' not allowed to explain decisions", operator);
+    }
+    let res = res
+        .error_for_status()
+        .context("failed to explain decision")?;
+    let explanation = decode::<Explanation>(res).context("failed to decode response")?;
+    println!("{}", explanation);
+    Ok(())
+}
+
+/// Parse the arguments of the program. When the first free argument is not a
+/// command, the `run` command is assumed, so that `ostiarius-client URL COMMAND`
+/// still works.
+fn parse_options() -> ClientOptions {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    // The first free argument is the first one the global options before it
+    // do not take as their value.
+    let index = (0..args.len()).find(|&index| {
+        !args[index].starts_with('-') && ClientOptions::parse_args_default(&args[..index]).is_ok()
+    });
+    let index = match index {
+        Some(index) if !is_command(&args[index]) => index,
+        _ => return ClientOptions::parse_args_default_or_exit(),
+    };
+    args.insert(index, "run".to_string());
+    ClientOptions::parse_args_default(&args).unwrap_or_else(|e| {
+        eprintln!("ostiarius-client: {}", e);
+        std::process::exit(2);
+    })
+}
+
+fn is_command(arg: &str) -> bool {
+    Command::command_list()
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.strip_prefix("  "))
+        .filter(|line| !line.starts_with(' '))
+        .filter_map(|line| line.split_whitespace().next())
+        .any(|name| name == arg)
+}
+
+fn main() -> anyhow::Result<()> {
+    let options = parse_options();
+    if options.version {
+        println!("ostiarius-client {}", env!("CARGO_PKG_VERSION"));
+        std::process::exit(0);
+    }
+    match &options.command {
+        Some(Command::Run(run_options)) => run(&options, run_options),
+        Some(Command::Action(action_options)) => action(&options, action_options),
+        Some(Command::Batch(batch_options)) => batch(&options, batch_options),
+        Some(Command::OpenSession(session_options)) => open_session(&options, session_options),
+        Some(Command::CloseSession(session_options)) => close_session(&options, session_options),
+        Some(Command::Sessions(sessions_options)) => sessions(&options, sessions_options),
+        Some(Command::RevokeSession(revoke_options)) => revoke_session(&options, revoke_options),
+        Some(Command::Request(request_options)) => request(&options, request_options),
+        Some(Command::Redeem(redeem_options)) => redeem(&options, redeem_options),
+        Some(Command::Trust(trust_options)) => trust(&options, trust_options),
+        Some(Command::Pending(pending_options)) => pending(&options, pending_options),
+        Some(Command::Approve(approve_options)) => decide(&options, approve_options, true),
+        Some(Command::Deny(approve_options)) => decide(&options, approve_options, false),
+        Some(Command::Explain(explain_options)) => explain(&options, explain_options),
+        None => {
+            eprintln!("Usage: ostiarius-client [OPTIONS] COMMAND [ARGS]");
+            eprintln!();
+            eprintln!("{}", ClientOptions::usage());
+            eprintln!();
+            eprintln!("Available commands:");
+            eprintln!("{}", ClientOptions::command_list().unwrap_or_default());
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::state;
+use anyhow::Context;
+use openssl::base64;
+use ostiarius_core::TOKEN_SIZE;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PendingRequest {
+    pub name: String,
+    pub command: String,
+    pub token: String,
+}
+
+pub fn pending_path(id: &Uuid) -> anyhow::Result<PathBuf> {
+    Ok(state::state_dir()?
+        .join("requests")
+        .join(format!("{}.json", id)))
+}
+
+impl PendingRequest {
+    pub fn new(name: &str, command: &str, token: &[u8]) -> Self {
+        PendingRequest {
+            name: name.to_string(),
+            command: command.to_string(),
+            token: base64::encode_block(token),
+        }
+    }
+
+    pub fn token(&self) -> anyhow::Result<[u8; TOKEN_SIZE]> {
+        let token = base64::decode_block(&self.token)?;
+        token
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("invalid token size"))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        match state::load(path)? {
+            Some(data) => {
+                let pending =
+                    serde_json::from_slice(&data).context("failed to decode pending request")?;
+                Ok(Some(pending))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        state::save_private(path, &serde_json::to_vec(self)?)
+    }
+
+    pub fn remove(path: &Path) -> anyhow::Result<()> {
+        state::remove(path)
+    }
+}
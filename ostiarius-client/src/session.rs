@@ -0,0 +1,51 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::state;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use openssl::hash::MessageDigest;
+use ostiarius_core::Session;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StoredSession {
+    pub url: String,
+    pub checked: DateTime<Utc>,
+    pub session: Session,
+}
+
+pub fn session_path(url: &str) -> anyhow::Result<PathBuf> {
+    let digest = openssl::hash::hash(MessageDigest::sha256(), url.as_bytes())?;
+    let name = digest
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    Ok(state::state_dir()?
+        .join("sessions")
+        .join(format!("{}.json", name)))
+}
+
+impl StoredSession {
+    pub fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        match state::load(path)? {
+            Some(data) => {
+                let stored = serde_json::from_slice(&data).context("failed to decode session")?;
+                Ok(Some(stored))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        state::save_private(path, &serde_json::to_vec(self)?)
+    }
+
+    pub fn remove(path: &Path) -> anyhow::Result<()> {
+        state::remove(path)
+    }
+}
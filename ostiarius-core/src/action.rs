@@ -0,0 +1,256 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Named actions: command templates with typed parameters.
+//!
+//! An action gives a `program` and templates for its `args`, in which `{name}`
+//! is replaced by the value of the parameter `name` (`{{` and `}}` stand for
+//! literal braces). Each parameter is declared in `params` with a pattern its
+//! values must match, as for the arguments of a command pattern. A client
+//! requests an action by name with the values of all its parameters, and the
+//! server returns the rendered arguments in the authorization, so that the
+//! client never builds the command line itself. Values must not be empty nor
+//! contain whitespace or control characters.
+
+use crate::matcher::{ArgPattern, CommandPattern};
+use crate::{Error, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Values of the parameters of an action, by name.
+pub type Params = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment<'a> {
+    Literal(String),
+    Param(&'a str),
+}
+
+/// Split a template into literal text and parameters.
+fn segments(template: &str) -> std::result::Result<Vec<Segment<'_>>, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '{' if rest.starts_with("{{") => {
+                literal.push('{');
+                rest = &rest[2..];
+            }
+            '}' if rest.starts_with("}}") => {
+                literal.push('}');
+                rest = &rest[2..];
+            }
+            '{' => {
+                let end = rest
+                    .find('}')
+                    .ok_or_else(|| format!("unclosed parameter in '{}'", template))?;
+                let name = &rest[1..end];
+                if name.is_empty() || name.contains('{') {
+                    return Err(format!("invalid parameter in '{}'", template));
+                }
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Param(name));
+                rest = &rest[end + 1..];
+            }
+            '}' => return Err(format!("unmatched '}}' in '{}'", template)),
+            c => {
+                literal.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if !literal.is_empty() || segments.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+/// Command template run with the parameters given by a client.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Action {
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub params: BTreeMap<String, ArgPattern>,
+}
+
+impl Action {
+    /// Check that the templates are well-formed, and that they use all the
+    /// declared parameters and only them.
+    pub fn validate(&self, name: &str) -> Result<()> {
+        let invalid =
+            |message: String| Error::InvalidPolicy(format!("action '{}': {}", name, message));
+        if crate::matcher::argv(&self.program).len() != 1 {
+            return Err(invalid(format!("invalid program '{}'", self.program)));
+        }
+        let mut used = Vec::new();
+        for template in &self.args {
+            for segment in segments(template).map_err(invalid)? {
+                if let Segment::Param(param) = segment {
+                    if !self.params.contains_key(param) {
+                        return Err(invalid(format!("unknown parameter '{}'", param)));
+                    }
+                    used.push(param);
+                }
+            }
+        }
+        match self
+            .params
+            .keys()
+            .find(|param| !used.contains(&param.as_str()))
+        {
+            Some(param) => Err(invalid(format!("unused parameter '{}'", param))),
+            None => Ok(()),
+        }
+    }
+
+    /// Validate the values of the parameters and render the arguments of the
+    /// command, program included.
+    pub fn render(&self, params: &Params) -> Result<Vec<String>> {
+        if let Some(param) = params
+            .keys()
+            .find(|param| !self.params.contains_key(*param))
+        {
+            return Err(Error::InvalidParameter(format!(
+                "unknown parameter '{}'",
+                param
+            )));
+        }
+        for (param, pattern) in &self.params {
+            let value = params
+                .get(param)
+                .ok_or_else(|| Error::InvalidParameter(format!("missing parameter '{}'", param)))?;
+            let safe =
+                !value.is_empty() && !value.chars().any(|c| c.is_whitespace() || c.is_control());
+            if !safe || !pattern.matches(value) {
+                let message = format!("invalid value '{}' for parameter '{}'", value, param);
+                return Err(Error::InvalidParameter(message));
+            }
+        }
+        let mut argv = vec![self.program.clone()];
+        for template in &self.args {
+            let arg = segments(template)
+                .map_err(Error::InvalidPolicy)?
+                .into_iter()
+                .map(|segment| match segment {
+                    Segment::Literal(literal) => literal,
+                    Segment::Param(param) => params[param].clone(),
+                })
+                .collect::<String>();
+            argv.push(arg);
+        }
+        Ok(argv)
+    }
+
+    /// Pattern matching the commands rendered from this action. A parameter
+    /// making up a whole argument keeps its pattern, while an argument mixing
+    /// text and parameters is approximated by a glob.
+    pub fn pattern(&self) -> CommandPattern {
+        let args = self
+            .args
+            .iter()
+            .map(|template| match segments(template).as_deref() {
+                Ok([Segment::Literal(literal)]) => ArgPattern::Literal(literal.clone()),
+                Ok([Segment::Param(param)]) => self.params[*param].clone(),
+                Ok(segments) => ArgPattern::Glob {
+                    glob: segments
+                        .iter()
+                        .map(|segment| match segment {
+                            Segment::Literal(literal) => literal.as_str(),
+                            Segment::Param(_) => "*",
+                        })
+                        .collect(),
+                },
+                Err(_) => ArgPattern::Literal(template.clone()),
+            })
+            .collect();
+        CommandPattern {
+            program: self.program.clone(),
+            args,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action() -> Action {
+        toml::from_str(
+            r#"
+            program = "/usr/bin/systemctl"
+            args = ["restart", "{service}.service", "--lines={{{lines}}}"]
+            params.service = { type = "enum", values = ["nginx", "sshd"] }
+            params.lines = { type = "integer", min = 1, max = 100 }
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn params(values: &[(&str, &str)]) -> Params {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn render_action() {
+        let action = action();
+        assert!(action.validate("restart-service").is_ok());
+        let argv = action
+            .render(&params(&[("service", "nginx"), ("lines", "20")]))
+            .unwrap();
+        assert_eq!(
+            argv,
+            vec![
+                "/usr/bin/systemctl",
+                "restart",
+                "nginx.service",
+                "--lines={20}"
+            ]
+        );
+        assert!(action.pattern().matches(&argv.join(" ")));
+        for values in [
+            vec![("service", "nginx")],
+            vec![("service", "cron"), ("lines", "20")],
+            vec![("service", "nginx"), ("lines", "200")],
+            vec![("service", "nginx"), ("lines", "20"), ("user", "root")],
+        ] {
+            let result = action.render(&params(&values));
+            assert!(matches!(result, Err(Error::InvalidParameter(_))));
+        }
+    }
+
+    #[test]
+    fn reject_unsafe_values() {
+        let mut action = action();
+        action
+            .params
+            .insert("service".to_string(), ArgPattern::Glob { glob: "*".into() });
+        for value in ["", "nginx sshd", "nginx\n"] {
+            let result = action.render(&params(&[("service", value), ("lines", "1")]));
+            assert!(matches!(result, Err(Error::InvalidParameter(_))));
+        }
+    }
+
+    #[test]
+    fn validate_templates() {
+        let mut action = action();
+        action.args.push("{user}".to_string());
+        assert!(action.validate("restart-service").is_err());
+        let mut action = self::action();
+        action.args.pop();
+        assert!(action.validate("restart-service").is_err());
+        for template in ["{service", "service}", "{}"] {
+            assert!(segments(template).is_err());
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::action::Params;
+use crate::{ApprovalMode, Authorization, Checker, CommandRule, Error, Request, Result, Signed};
+use chrono::{DateTime, Utc};
+use openssl::{pkey::Public, rsa::Rsa};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Person allowed to approve requests for commands requiring a manual approval.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Operator {
+    pub name: String,
+    #[serde(default)]
+    pub pub_key: String,
+    #[serde(default)]
+    pub(crate) pub_key_file: Option<PathBuf>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+impl Operator {
+    pub fn public_key(&self) -> Result<Rsa<Public>> {
+        Ok(Rsa::public_key_from_pem(self.pub_key.as_bytes())?)
+    }
+
+    pub fn is_member(&self, group: &str) -> bool {
+        self.groups.iter().any(|g| g == group)
+    }
+}
+
+/// Request waiting for the approval of operators.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PendingAuthorization {
+    pub id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub name: String,
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Params>,
+    #[serde(with = "crate::wire::binary")]
+    pub challenge: String,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::wire::address"
+    )]
+    pub source: Option<IpAddr>,
+    pub required: u32,
+    #[serde(default)]
+    pub approvals: Vec<Approval>,
+}
+
+/// Decision of an operator about a pending request, signed with its key.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Approval {
+    pub request_id: Uuid,
+    pub operator: String,
+    pub approved: bool,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default, with = "crate::wire::binary")]
+    pub signature: String,
+}
+
+impl Signed for Approval {
+    fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    fn signature_mut(&mut self) -> &mut String {
+        &mut self.signature
+    }
+}
+
+impl Approval {
+    pub fn new(request_id: Uuid, operator: &str, approved: bool) -> Self {
+        Approval {
+            request_id,
+            operator: operator.to_string(),
+            approved,
+            timestamp: chrono::offset::Utc::now(),
+            signature: String::new(),
+        }
+    }
+}
+
+impl Checker {
+    fn manual_rule(
+        &self,
+        name: &str,
+        source: Option<IpAddr>,
+        command: &str,
+        params: Option<&Params>,
+    ) -> Result<&CommandRule> {
+        match self.find_rule(name, source, command, params) {
+            Ok((_, rule)) if rule.approval == ApprovalMode::Manual => Ok(rule),
+            _ => Err(Error::Unauthorized),
+        }
+    }
+
+    /// Put aside a request for a command requiring a manual approval.
+    pub fn defer(&self, request: &Request, source: Option<IpAddr>) -> Result<PendingAuthorization> {
+        self.decrypt_challenge(&request.challenge)?;
+        let rule = self.manual_rule(
+            &request.name,
+            source,
+            &request.command,
+            request.params.as_ref(),
+        )?;
+        rule.check_schedule(self.now())?;
+        let pending = PendingAuthorization {
+            id: Uuid::new_v4(),
+            timestamp: self.now(),
+            name: request.name.clone(),
+            command: request.command.clone(),
+            params: request.params.clone(),
+            challenge: request.challenge.clone(),
+            source,
+            required: rule.approvals.max(1),
+            approvals: Vec::new(),
+        };
+        Ok(pending)
+    }
+
+    /// Check that a decision about a pending request was signed by an
+    /// operator allowed to approve it, who has not already approved it.
+    pub fn verify_approval(
+        &self,
+        pending: &PendingAuthorization,
+        approval: &Approval,
+    ) -> Result<()> {
+        let operator = self
+            .authorizations()
+            .operators()
+            .iter()
+            .find(|operator| operator.name == approval.operator)
+            .ok_or(Error::Unauthorized)?;
+        if approval.request_id != pending.id || !approval.verify(&operator.public_key()?)? {
+            return Err(Error::Unauthorized);
+        }
+        let rule = self.manual_rule(
+            &pending.name,
+            pending.source,
+            &pending.command,
+            pending.params.as_ref(),
+        )?;
+        if let Some(group) = &rule.approvers {
+            if !operator.is_member(group) {
+                return Err(Error::Unauthorized);
+            }
+        }
+        if pending
+            .approvals
+            .iter()
+            .any(|other| other.operator == approval.operator)
+        {
+            return Err(Error::DuplicateApproval(approval.operator.clone()));
+        }
+        Ok(())
+    }
+
+    /// Record the approval of an operator. Once enough operators have approved
+    /// the request, grant it with the full chain of approvals.
+    pub fn approve(
+        &self,
+        pending: &mut PendingAuthorization,
+        approval: Approval,
+    ) -> Result<Option<Authorization>> {
+        self.verify_approval(pending, &approval)?;
+        if !approval.approved {
+            return Err(Error::Unauthorized);
+        }
+        pending.approvals.push(approval);
+        if (pending.approvals.len() as u32) < pending.required {
+            return Ok(None);
+        }
+        let (client, rule) = self.find_rule(
+            &pending.name,
+            pending.source,
+            &pending.command,
+            pending.params.as_ref(),
+        )?;
+        rule.check_schedule(self.now())?;
+        let request = Request {
+            name: pending.name.clone(),
+            command: pending.command.clone(),
+            params: pending.params.clone(),
+            challenge: pending.challenge.clone(),
+        };
+        let challenge = self.decrypt_challenge(&request.challenge)?;
+        let authorization = self.grant(
+            (client, rule),
+            &request,
+            pending.source,
+            &challenge,
+            pending.id,
+            pending.approvals.clone(),
+        )?;
+        Ok(Some(authorization))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authorization::tests::{create_checker, create_requester};
+    use crate::RsaPrivateKey;
+    use std::path::PathBuf;
+
+    fn signed_approval(id: Uuid, operator: &str, key: &str) -> Approval {
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "..", "tests", key]
+            .iter()
+            .collect();
+        let key = RsaPrivateKey::from_uri(&format!("file://{}", path.display())).unwrap();
+        let mut approval = Approval::new(id, operator, true);
+        approval.sign(&key).unwrap();
+        approval
+    }
+
+    #[test]
+    fn defer_and_approve() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 1", "poweroff").unwrap();
+        let mut pending = checker.defer(&request, None).unwrap();
+        let approval = signed_approval(pending.id, "Operator 1", "operator1.privkey.pem");
+        let authorization = checker.approve(&mut pending, approval).unwrap().unwrap();
+        assert_eq!(authorization.id, pending.id);
+        assert_eq!(authorization.approvals[0].operator, "Operator 1");
+        assert!(requester.check(&authorization).unwrap());
+    }
+
+    #[test]
+    fn defer_automatic_command() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 1", "date").unwrap();
+        assert!(matches!(
+            checker.defer(&request, None),
+            Err(Error::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn approve_with_invalid_signature() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 1", "poweroff").unwrap();
+        let mut pending = checker.defer(&request, None).unwrap();
+        let mut approval = signed_approval(pending.id, "Operator 1", "operator1.privkey.pem");
+        approval.approved = false;
+        assert!(matches!(
+            checker.verify_approval(&pending, &approval),
+            Err(Error::Unauthorized)
+        ));
+        let approval = signed_approval(pending.id, "Operator 2", "operator1.privkey.pem");
+        let result = checker.approve(&mut pending, approval);
+        assert!(matches!(result, Err(Error::Unauthorized)));
+    }
+
+    #[test]
+    fn approve_with_quorum() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 1", "rm -rf /var/cache").unwrap();
+        let mut pending = checker.defer(&request, None).unwrap();
+        assert_eq!(pending.required, 2);
+        let approval = signed_approval(pending.id, "Operator 1", "operator1.privkey.pem");
+        assert!(checker
+            .approve(&mut pending, approval.clone())
+            .unwrap()
+            .is_none());
+        let result = checker.approve(&mut pending, approval);
+        assert!(matches!(result, Err(Error::DuplicateApproval(_))));
+        let approval = signed_approval(pending.id, "Operator 2", "operator2.privkey.pem");
+        let authorization = checker.approve(&mut pending, approval).unwrap().unwrap();
+        let operators = authorization
+            .approvals
+            .iter()
+            .map(|approval| approval.operator.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(operators, vec!["Operator 1", "Operator 2"]);
+        assert!(requester.check(&authorization).unwrap());
+    }
+
+    #[test]
+    fn approve_outside_group() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 1", "shutdown").unwrap();
+        let mut pending = checker.defer(&request, None).unwrap();
+        let approval = signed_approval(pending.id, "Operator 1", "operator1.privkey.pem");
+        let result = checker.approve(&mut pending, approval);
+        assert!(matches!(result, Err(Error::Unauthorized)));
+        assert!(pending.approvals.is_empty());
+    }
+}
@@ -0,0 +1,75 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::{Error, Result};
+use openssl::base64;
+
+const LINE_LENGTH: usize = 64;
+
+/// Encode data as text, between `-----BEGIN OSTIARIUS <LABEL>-----` and
+/// `-----END OSTIARIUS <LABEL>-----` lines, like PEM.
+pub fn encode(label: &str, data: &[u8]) -> String {
+    let encoded = base64::encode_block(data);
+    let mut armored = format!("-----BEGIN OSTIARIUS {}-----\n", label);
+    for chunk in encoded.as_bytes().chunks(LINE_LENGTH) {
+        armored.push_str(&String::from_utf8_lossy(chunk));
+        armored.push('\n');
+    }
+    armored.push_str(&format!("-----END OSTIARIUS {}-----\n", label));
+    armored
+}
+
+pub fn decode(label: &str, text: &str) -> Result<Vec<u8>> {
+    let begin = format!("-----BEGIN OSTIARIUS {}-----", label);
+    let end = format!("-----END OSTIARIUS {}-----", label);
+    let mut lines = text.lines().map(str::trim);
+    lines
+        .by_ref()
+        .find(|line| *line == begin)
+        .ok_or_else(|| Error::InvalidArmor(format!("missing '{}'", begin)))?;
+    let mut encoded = String::new();
+    let mut terminated = false;
+    for line in lines {
+        if line == end {
+            terminated = true;
+            break;
+        }
+        encoded.push_str(line);
+    }
+    if !terminated {
+        return Err(Error::InvalidArmor(format!("missing '{}'", end)));
+    }
+    base64::decode_block(&encoded).map_err(|_| Error::InvalidArmor("invalid base64".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        let data = (0..=255).collect::<Vec<u8>>();
+        let armored = encode("TEST", &data);
+        assert!(armored.starts_with("-----BEGIN OSTIARIUS TEST-----\n"));
+        assert!(armored.lines().all(|line| line.len() <= LINE_LENGTH + 10));
+        let text = format!("Some header\n\n{}\ntrailer\n", armored);
+        assert_eq!(decode("TEST", &text).unwrap(), data);
+    }
+
+    #[test]
+    fn decode_invalid() {
+        let armored = encode("TEST", b"data");
+        assert!(matches!(
+            decode("OTHER", &armored),
+            Err(Error::InvalidArmor(_))
+        ));
+        let truncated = armored.lines().take(2).collect::<Vec<_>>().join("\n");
+        assert!(matches!(
+            decode("TEST", &truncated),
+            Err(Error::InvalidArmor(_))
+        ));
+    }
+}
@@ -4,23 +4,43 @@
 // SPDX-License-Identifier: MIT
 //
 
-use crate::{Error, PrivateKey, Result, RsaPrivateKey};
+use crate::action::{Action, Params};
+use crate::exec::{EnvPolicy, ExecContext, Script};
+use crate::limits::RateLimit;
+use crate::matcher::{ArgPattern, CommandPattern};
+use crate::policy::{MemoryProvider, PolicyFormat, PolicyProvider};
+use crate::roles::{Role, Roles};
+use crate::schedule::{Clock, Schedule, SystemClock};
+use crate::{Approval, Error, Operator, PrivateKey, Result, RsaPrivateKey, SessionPolicy, Signed};
 use chrono::{DateTime, Utc};
+use ipnet::IpNet;
 use openssl::{
     base64,
-    pkey::Public,
+    hash::MessageDigest,
+    pkey::{PKey, Public},
     rsa::{Padding, Rsa},
+    sign::Signer,
 };
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use toml;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use uuid::Uuid;
 
+/// Size of the random token exchanged in challenges, in bytes.
+pub const TOKEN_SIZE: usize = 32;
+
+/// Request for a command. For an action, `command` is the name of the action
+/// and `params` the values of its parameters.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Request {
     pub name: String,
     pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Params>,
+    #[serde(with = "crate::wire::binary")]
     pub challenge: String,
 }
 
@@ -28,16 +48,20 @@ pub struct Request {
 pub struct Requester {
     priv_key: RsaPrivateKey,
     checker_pub_key: Rsa<Public>,
-    token: [u8; 32],
+    token: [u8; TOKEN_SIZE],
 }
 
 impl Requester {
     pub fn new<P: AsRef<Path>>(priv_key_uri: &str, checker_pub_key_path: P) -> Result<Self> {
-        let priv_key = RsaPrivateKey::from_uri(priv_key_uri)?;
         let checker_pub_key = std::fs::read(checker_pub_key_path)?;
         let checker_pub_key = Rsa::public_key_from_pem(&checker_pub_key)?;
+        Self::with_pub_key(priv_key_uri, checker_pub_key)
+    }
+
+    pub fn with_pub_key(priv_key_uri: &str, checker_pub_key: Rsa<Public>) -> Result<Self> {
+        let priv_key = RsaPrivateKey::from_uri(priv_key_uri)?;
         let mut rng = rand::thread_rng();
-        let mut token = [0u8; 32];
+        let mut token = [0u8; TOKEN_SIZE];
         rng.fill(&mut token);
         let requester = Requester {
             priv_key,
@@ -47,59 +71,574 @@ impl Requester {
         Ok(requester)
     }
 
+    pub fn token(&self) -> &[u8; TOKEN_SIZE] {
+        &self.token
+    }
+
+    pub fn with_token(self, token: [u8; TOKEN_SIZE]) -> Self {
+        Requester { token, ..self }
+    }
+
     pub fn make(&self, name: &str, command: &str) -> Result<Request> {
         let request = Request {
             name: name.to_string(),
             command: command.to_string(),
+            params: None,
             challenge: self.make_challenge()?,
         };
         Ok(request)
     }
 
-    fn make_challenge(&self) -> Result<String> {
+    pub fn make_action(&self, name: &str, action: &str, params: Params) -> Result<Request> {
+        let request = Request {
+            params: Some(params),
+            ..self.make(name, action)?
+        };
+        Ok(request)
+    }
+
+    pub(crate) fn make_challenge(&self) -> Result<String> {
         let mut challenge: Vec<u8> = vec![0; self.checker_pub_key.size() as usize];
-        let _ = self
-            .checker_pub_key
-            .public_encrypt(&self.token, &mut challenge, Padding::PKCS1)?;
+        let _ = self.checker_pub_key.public_encrypt(
+            &self.token,
+            &mut challenge,
+            Padding::PKCS1_OAEP,
+        )?;
         Ok(base64::encode_block(&challenge))
     }
 
     pub fn check(&self, authorization: &Authorization) -> Result<bool> {
-        let token = base64::decode_block(&authorization.token)?;
+        Ok(self.verify(authorization)? && self.check_token(&authorization.token)?)
+    }
+
+    pub fn verify<T: Signed>(&self, item: &T) -> Result<bool> {
+        item.verify(&self.checker_pub_key)
+    }
+
+    pub(crate) fn check_token(&self, token: &str) -> Result<bool> {
+        let token = base64::decode_block(token)?;
         let mut challenge: Vec<u8> = vec![0; self.priv_key.size()];
         let size = self.priv_key.decrypt(&token, &mut challenge)?;
         Ok(challenge[..size] == self.token)
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApprovalMode {
+    #[default]
+    Auto,
+    Manual,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RuleEntry {
+    action: Option<String>,
+    command: Option<String>,
+    program: Option<String>,
+    #[serde(default)]
+    args: Vec<ArgPattern>,
+    #[serde(default)]
+    approval: ApprovalMode,
+    #[serde(default = "default_approvals")]
+    approvals: u32,
+    approvers: Option<String>,
+    schedule: Option<Schedule>,
+    rate_limit: Option<RateLimit>,
+    daily_quota: Option<u32>,
+    user: Option<String>,
+    group: Option<String>,
+    cwd: Option<String>,
+    #[serde(default)]
+    env: EnvPolicy,
+    executable: Option<String>,
+    sha256: Option<String>,
+    script: Option<Script>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum CommandEntry {
+    Command(String),
+    Rule(Box<RuleEntry>),
+}
+
+fn default_approvals() -> u32 {
+    1
+}
+
+/// Command allowed to a client, given either as a plain string or as a table
+/// with additional settings. The table gives either a `command`, a `program`
+/// with patterns for its `args`, or the name of an `action`. With a manual approval, `approvals`
+/// is the number of distinct operators, members of the `approvers` group if
+/// any, who must approve each run. With a `schedule`, the command is only
+/// allowed during its time windows. The number of requests for the command can
+/// be limited with a `rate_limit` and a `daily_quota`. The `user`, `group`,
+/// `cwd`, `env`, `executable`, `sha256` and `script` settings give the context
+/// in which the client executes the command.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "CommandEntry")]
+pub struct CommandRule {
+    pub pattern: CommandPattern,
+    pub action: Option<String>,
+    pub approval: ApprovalMode,
+    pub approvals: u32,
+    pub approvers: Option<String>,
+    pub schedule: Option<Schedule>,
+    pub rate_limit: Option<RateLimit>,
+    pub daily_quota: Option<u32>,
+    pub exec: ExecContext,
+}
+
+impl CommandRule {
+    /// Whether the rule sets conditions, or a context of execution, that the
+    /// client can not enforce on its own within a session.
+    pub fn is_constrained(&self) -> bool {
+        self.action.is_some()
+            || self.approval == ApprovalMode::Manual
+            || self.schedule.is_some()
+            || self.rate_limit.is_some()
+            || self.daily_quota.is_some()
+            || !self.exec.is_empty()
+    }
+
+    /// Fail with `Error::OutsideTimeWindow` if the command is not allowed at
+    /// the given time.
+    pub fn check_schedule(&self, time: DateTime<Utc>) -> Result<()> {
+        match &self.schedule {
+            Some(schedule) => schedule.check(time),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TryFrom<CommandEntry> for CommandRule {
+    type Error = String;
+
+    fn try_from(entry: CommandEntry) -> std::result::Result<Self, Self::Error> {
+        match entry {
+            CommandEntry::Command(command) => Ok(CommandRule {
+                pattern: CommandPattern::literal(&command),
+                action: None,
+                approval: ApprovalMode::Auto,
+                approvals: default_approvals(),
+                approvers: None,
+                schedule: None,
+                rate_limit: None,
+                daily_quota: None,
+                exec: ExecContext::default(),
+            }),
+            CommandEntry::Rule(rule) => {
+                let RuleEntry {
+                    action,
+                    command,
+                    program,
+                    args,
+                    approval,
+                    approvals,
+                    approvers,
+                    schedule,
+                    rate_limit,
+                    daily_quota,
+                    user,
+                    group,
+                    cwd,
+                    env,
+                    executable,
+                    sha256,
+                    script,
+                } = *rule;
+                // The pattern of an action is set once the actions are known.
+                let pattern = match &action {
+                    Some(action) if command.is_none() && program.is_none() && args.is_empty() => {
+                        CommandPattern::literal(action)
+                    }
+                    Some(_) => return Err("expected either `action` or a command".into()),
+                    None => CommandPattern::from_parts(command, program, args)?,
+                };
+                let exec = ExecContext {
+                    user,
+                    group,
+                    cwd,
+                    env,
+                    executable,
+                    sha256: sha256.map(|digest| digest.to_ascii_lowercase()),
+                    script,
+                };
+                exec.validate()?;
+                Ok(CommandRule {
+                    pattern,
+                    action,
+                    approval,
+                    approvals,
+                    approvers,
+                    schedule,
+                    rate_limit,
+                    daily_quota,
+                    exec,
+                })
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct AuthorizedClient {
     pub name: String,
+    #[serde(default)]
     pub pub_key: String,
-    pub commands: Vec<String>,
+    #[serde(default)]
+    pub_key_file: Option<PathBuf>,
+    #[serde(default)]
+    pub sources: Vec<IpNet>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub commands: Vec<CommandRule>,
+    #[serde(default)]
+    pub deny: Vec<CommandPattern>,
+    pub session: Option<SessionPolicy>,
+    pub rate_limit: Option<RateLimit>,
+    pub daily_quota: Option<u32>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+impl AuthorizedClient {
+    pub fn denies(&self, command: &str) -> bool {
+        self.deny.iter().any(|pattern| pattern.matches(command))
+    }
+
+    /// Find the rule allowing a command, unless the command is denied.
+    pub fn rule(&self, command: &str) -> Option<&CommandRule> {
+        if self.denies(command) {
+            return None;
+        }
+        self.commands
+            .iter()
+            .find(|rule| rule.action.is_none() && rule.pattern.matches(command))
+    }
+
+    /// Find the rule allowing an action, whatever its parameters.
+    pub fn action_rule(&self, action: &str) -> Option<&CommandRule> {
+        self.commands
+            .iter()
+            .find(|rule| rule.action.as_deref() == Some(action))
+    }
+
+    pub fn is_allowed(&self, command: &str) -> bool {
+        self.rule(command).is_some()
+    }
+
+    /// Tell whether requests may come from an address. Clients restricted to
+    /// some networks are denied when the address is unknown.
+    pub fn allows_source(&self, source: Option<IpAddr>) -> bool {
+        match source {
+            _ if self.sources.is_empty() => true,
+            Some(addr) => self.sources.iter().any(|net| net.contains(&addr)),
+            None => false,
+        }
+    }
+
+    pub(crate) fn encrypt(&self, data: &[u8]) -> Result<String> {
+        let pub_key: Rsa<Public> = Rsa::public_key_from_pem(self.pub_key.as_bytes())?;
+        let mut token: Vec<u8> = vec![0; pub_key.size() as usize];
+        let _size = pub_key.public_encrypt(data, &mut token, Padding::PKCS1_OAEP)?;
+        Ok(base64::encode_block(&token))
+    }
+}
+
+/// Read a public key given as a file, relative to `base`, and check it.
+fn load_pub_key(
+    owner: &str,
+    pub_key: &mut String,
+    pub_key_file: &mut Option<PathBuf>,
+    base: &Path,
+) -> Result<()> {
+    match (pub_key.is_empty(), pub_key_file.take()) {
+        (true, Some(file)) => *pub_key = std::fs::read_to_string(base.join(file))?,
+        (false, Some(_)) => {
+            let message = format!("both pub_key and pub_key_file given for {}", owner);
+            return Err(Error::InvalidPolicy(message));
+        }
+        (true, None) => {
+            let message = format!("missing pub_key or pub_key_file for {}", owner);
+            return Err(Error::InvalidPolicy(message));
+        }
+        (false, None) => {}
+    }
+    Rsa::public_key_from_pem(pub_key.as_bytes())
+        .map_err(|_| Error::InvalidPolicy(format!("invalid public key of {}", owner)))?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct PolicyFile {
+    #[serde(default)]
+    clients: Vec<AuthorizedClient>,
+    #[serde(default)]
+    operators: Vec<Operator>,
+    #[serde(default)]
+    command_sets: BTreeMap<String, Vec<CommandRule>>,
+    #[serde(default)]
+    roles: BTreeMap<String, Role>,
+    #[serde(default)]
+    actions: BTreeMap<String, Action>,
+    #[serde(default)]
+    deny: Vec<CommandPattern>,
+}
+
+impl PolicyFile {
+    fn read(path: &Path, format: PolicyFormat) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut file: PolicyFile = format.parse(&contents)?;
+        file.load_files(path.parent().unwrap_or_else(|| Path::new("")))?;
+        Ok(file)
+    }
+
+    /// Read the public keys and the scripts given as files, relative to
+    /// `base`.
+    fn load_files(&mut self, base: &Path) -> Result<()> {
+        for client in self.clients.iter_mut() {
+            let owner = format!("client '{}'", client.name);
+            load_pub_key(&owner, &mut client.pub_key, &mut client.pub_key_file, base)?;
+        }
+        for operator in self.operators.iter_mut() {
+            let owner = format!("operator '{}'", operator.name);
+            load_pub_key(
+                &owner,
+                &mut operator.pub_key,
+                &mut operator.pub_key_file,
+                base,
+            )?;
+        }
+        let rules = self
+            .clients
+            .iter_mut()
+            .flat_map(|client| client.commands.iter_mut())
+            .chain(self.command_sets.values_mut().flatten())
+            .chain(
+                self.roles
+                    .values_mut()
+                    .flat_map(|role| role.commands.iter_mut()),
+            );
+        for rule in rules {
+            if let Some(script) = &mut rule.exec.script {
+                script.load(base)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, other: PolicyFile) -> Result<()> {
+        self.clients.extend(other.clients);
+        self.operators.extend(other.operators);
+        self.deny.extend(other.deny);
+        for (name, commands) in other.command_sets {
+            if self.command_sets.insert(name.clone(), commands).is_some() {
+                let message = format!("command set '{}' is defined twice", name);
+                return Err(Error::InvalidPolicy(message));
+            }
+        }
+        for (name, role) in other.roles {
+            if self.roles.insert(name.clone(), role).is_some() {
+                let message = format!("role '{}' is defined twice", name);
+                return Err(Error::InvalidPolicy(message));
+            }
+        }
+        for (name, action) in other.actions {
+            if self.actions.insert(name.clone(), action).is_some() {
+                let message = format!("action '{}' is defined twice", name);
+                return Err(Error::InvalidPolicy(message));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Authorized clients and operators, with the roles of the clients expanded
+/// into their lists of commands.
+///
+/// A command is denied to a client if it matches any `deny` pattern of the
+/// client, of its roles or of the whole policy, whatever the commands allowed.
+/// Otherwise, the first rule matching the command among those of the client,
+/// then those of its roles, applies. An action is checked the same way, once
+/// rendered with its parameters, but only against the rules naming it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "PolicyFile")]
 pub struct Authorizations {
     clients: Vec<AuthorizedClient>,
+    operators: Vec<Operator>,
+    actions: BTreeMap<String, Action>,
+}
+
+impl TryFrom<PolicyFile> for Authorizations {
+    type Error = Error;
+
+    fn try_from(mut file: PolicyFile) -> Result<Self> {
+        // Files not read yet are relative to the current directory.
+        file.load_files(Path::new(""))?;
+        let roles = Roles::new(&file.command_sets, &file.roles);
+        roles.validate()?;
+        for (name, action) in &file.actions {
+            action.validate(name)?;
+        }
+        let mut clients = file.clients;
+        for client in clients.iter_mut() {
+            roles.expand(client)?;
+            client.deny.extend(file.deny.iter().cloned());
+            for rule in client.commands.iter_mut() {
+                if let Some(name) = &rule.action {
+                    let action = file.actions.get(name).ok_or_else(|| {
+                        let message =
+                            format!("unknown action '{}' for client '{}'", name, client.name);
+                        Error::InvalidPolicy(message)
+                    })?;
+                    rule.pattern = action.pattern();
+                }
+            }
+        }
+        Ok(Authorizations {
+            clients,
+            operators: file.operators,
+            actions: file.actions,
+        })
+    }
 }
 
 impl Authorizations {
+    /// Load the authorizations from a TOML file, or from the fragments of a
+    /// directory.
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let contents = std::fs::read_to_string(path)?;
-        let authorizations = toml::from_str(&contents)?;
-        Ok(authorizations)
+        Authorizations::read(path, PolicyFormat::Toml)
+    }
+
+    /// Load the authorizations from a file in the given format, or from the
+    /// fragments of a directory in this format, merged in the order of their
+    /// names. Public keys given with `pub_key_file` are relative to the file
+    /// declaring them. Errors name the file causing them.
+    pub fn read<P: AsRef<Path>>(path: P, format: PolicyFormat) -> Result<Self> {
+        let mut policy = PolicyFile::default();
+        for file in format.files(path)? {
+            PolicyFile::read(&file, format)
+                .and_then(|fragment| policy.merge(fragment))
+                .map_err(|e| Error::InFile(file, Box::new(e)))?;
+        }
+        Authorizations::try_from(policy)
     }
 
     pub fn clients(&self) -> &Vec<AuthorizedClient> {
         &self.clients
     }
+
+    pub fn operators(&self) -> &Vec<Operator> {
+        &self.operators
+    }
+
+    pub fn actions(&self) -> &BTreeMap<String, Action> {
+        &self.actions
+    }
+
+    /// Render the arguments of an action with the values of its parameters.
+    pub fn render(&self, action: &str, params: &Params) -> Result<Vec<String>> {
+        self.actions
+            .get(action)
+            .ok_or_else(|| Error::CommandNotAllowed(action.to_string()))?
+            .render(params)
+    }
+
+    pub(crate) fn find_clients(
+        &self,
+        name: &str,
+        source: Option<IpAddr>,
+    ) -> Result<Vec<&AuthorizedClient>> {
+        let clients = self
+            .clients()
+            .iter()
+            .filter(|client| client.name == name)
+            .collect::<Vec<_>>();
+        if clients.is_empty() {
+            return Err(Error::UnknownClient(name.to_string()));
+        }
+        let clients = clients
+            .into_iter()
+            .filter(|client| client.allows_source(source))
+            .collect::<Vec<_>>();
+        if clients.is_empty() {
+            let source = source.map_or_else(|| "unknown".to_string(), |addr| addr.to_string());
+            return Err(Error::SourceNotAllowed(source));
+        }
+        Ok(clients)
+    }
+
+    /// Find the rule allowing a command, or an action if `params` are given.
+    pub(crate) fn find_rule(
+        &self,
+        name: &str,
+        source: Option<IpAddr>,
+        command: &str,
+        params: Option<&Params>,
+    ) -> Result<(&AuthorizedClient, &CommandRule)> {
+        let clients = self.find_clients(name, source)?;
+        let rendered = match params {
+            Some(params) => {
+                if !clients
+                    .iter()
+                    .any(|client| client.action_rule(command).is_some())
+                {
+                    return Err(Error::CommandNotAllowed(command.to_string()));
+                }
+                self.render(command, params)?.join(" ")
+            }
+            None => command.to_string(),
+        };
+        if clients.iter().any(|client| client.denies(&rendered)) {
+            return Err(Error::CommandDenied(rendered));
+        }
+        clients
+            .into_iter()
+            .find_map(|client| {
+                let rule = match params {
+                    Some(_) => client.action_rule(command),
+                    None => client.rule(command),
+                };
+                rule.map(|rule| (client, rule))
+            })
+            .ok_or_else(|| Error::CommandNotAllowed(command.to_string()))
+    }
+}
+
+/// Random key known only to the server, to derive values which look random to
+/// clients, yet are always the same for the same input.
+#[derive(Clone)]
+struct Secret([u8; 32]);
+
+impl Secret {
+    fn new() -> Self {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill(&mut key);
+        Secret(key)
+    }
+
+    fn derive(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let key = PKey::hmac(&self.0)?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+        signer.update(data)?;
+        Ok(signer.sign_to_vec()?)
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(..)")
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Checker {
     authorizations: Authorizations,
+    provider: Arc<dyn PolicyProvider>,
     priv_key: RsaPrivateKey,
+    clock: Arc<dyn Clock>,
+    secret: Secret,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -108,52 +647,214 @@ pub struct Authorization {
     pub timestamp: DateTime<Utc>,
     pub name: String,
     pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Params>,
+    /// Arguments rendered from an action, to be executed as is.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub argv: Vec<String>,
+    #[serde(with = "crate::wire::binary")]
     pub token: String,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::wire::address"
+    )]
+    pub source: Option<IpAddr>,
+    #[serde(default, skip_serializing_if = "ExecContext::is_empty")]
+    pub exec: ExecContext,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub approvals: Vec<Approval>,
+    #[serde(default, with = "crate::wire::binary")]
+    pub signature: String,
+}
+
+impl Signed for Authorization {
+    fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    fn signature_mut(&mut self) -> &mut String {
+        &mut self.signature
+    }
 }
 
 impl Checker {
     pub fn new(priv_key_uri: &str, authorizations: Authorizations) -> Result<Self> {
+        let provider = MemoryProvider::new(authorizations.clone());
         let priv_key = RsaPrivateKey::from_uri(priv_key_uri)?;
         let checker = Checker {
             authorizations,
+            provider: Arc::new(provider),
             priv_key,
+            clock: Arc::new(SystemClock),
+            secret: Secret::new(),
         };
         Ok(checker)
     }
 
-    pub fn check(&self, request: &Request) -> Result<Authorization> {
-        let data = base64::decode_block(&request.challenge)?;
-        let mut challenge: Vec<u8> = vec![0; self.priv_key.size()];
-        let size = self.priv_key.decrypt(&data, &mut challenge)?;
-        let client = self
-            .authorizations
-            .clients()
-            .iter()
-            .find(|client| {
-                client.name == request.name
-                    && client.commands.iter().any(|cmd| cmd == &request.command)
-            })
-            .ok_or(Error::Unauthorized)?;
-        let pub_key: Rsa<Public> = Rsa::public_key_from_pem(client.pub_key.as_bytes())?;
-        let mut token: Vec<u8> = vec![0; pub_key.size() as usize];
-        let _size = pub_key.public_encrypt(&challenge[0..size], &mut token, Padding::PKCS1)?;
-        let authorization = Authorization {
-            id: Uuid::new_v4(),
-            timestamp: chrono::offset::Utc::now(),
+    /// Create a checker enforcing the authorizations loaded from a provider.
+    pub fn with_provider(priv_key_uri: &str, provider: Arc<dyn PolicyProvider>) -> Result<Self> {
+        let authorizations = provider.load()?;
+        let priv_key = RsaPrivateKey::from_uri(priv_key_uri)?;
+        let checker = Checker {
+            authorizations,
+            provider,
+            priv_key,
+            clock: Arc::new(SystemClock),
+            secret: Secret::new(),
+        };
+        Ok(checker)
+    }
+
+    /// Use another source of time to enforce the schedules of the commands.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Checker { clock, ..self }
+    }
+
+    /// Create a checker enforcing other authorizations, with the same private
+    /// key, e.g. to reload them without asking again for its password.
+    pub fn with_authorizations(&self, authorizations: Authorizations) -> Self {
+        Checker {
+            authorizations,
+            provider: self.provider.clone(),
+            priv_key: self.priv_key.clone(),
+            clock: self.clock.clone(),
+            secret: self.secret.clone(),
+        }
+    }
+
+    /// Create a checker enforcing the authorizations loaded again from the
+    /// provider of this one.
+    pub fn reload(&self) -> Result<Self> {
+        Ok(self.with_authorizations(self.provider.load()?))
+    }
+
+    pub(crate) fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    pub fn public_key(&self) -> Result<Rsa<Public>> {
+        self.priv_key.public_key()
+    }
+
+    /// Check a request, sent from the `source` address if known, against the
+    /// authorizations. Fails with `Error::ApprovalRequired` if the command
+    /// must be approved by an operator.
+    pub fn check(&self, request: &Request, source: Option<IpAddr>) -> Result<Authorization> {
+        let challenge = self.decrypt_challenge(&request.challenge)?;
+        let (client, rule) = self.find_rule(
+            &request.name,
+            source,
+            &request.command,
+            request.params.as_ref(),
+        )?;
+        rule.check_schedule(self.now())?;
+        if rule.approval == ApprovalMode::Manual {
+            return Err(Error::ApprovalRequired);
+        }
+        self.grant(
+            (client, rule),
+            request,
+            source,
+            &challenge,
+            Uuid::new_v4(),
+            Vec::new(),
+        )
+    }
+
+    pub(crate) fn find_clients(
+        &self,
+        name: &str,
+        source: Option<IpAddr>,
+    ) -> Result<Vec<&AuthorizedClient>> {
+        self.authorizations.find_clients(name, source)
+    }
+
+    pub(crate) fn find_rule(
+        &self,
+        name: &str,
+        source: Option<IpAddr>,
+        command: &str,
+        params: Option<&Params>,
+    ) -> Result<(&AuthorizedClient, &CommandRule)> {
+        self.authorizations.find_rule(name, source, command, params)
+    }
+
+    pub(crate) fn grant(
+        &self,
+        (client, rule): (&AuthorizedClient, &CommandRule),
+        request: &Request,
+        source: Option<IpAddr>,
+        challenge: &[u8],
+        id: Uuid,
+        approvals: Vec<Approval>,
+    ) -> Result<Authorization> {
+        let argv = match &request.params {
+            Some(params) => self.authorizations.render(&request.command, params)?,
+            None => Vec::new(),
+        };
+        let mut authorization = Authorization {
+            id,
+            timestamp: self.now(),
             name: request.name.clone(),
             command: request.command.clone(),
-            token: base64::encode_block(&token),
+            params: request.params.clone(),
+            argv,
+            token: client.encrypt(challenge)?,
+            source,
+            exec: rule.exec.clone(),
+            approvals,
+            signature: String::new(),
         };
+        self.sign(&mut authorization)?;
         Ok(authorization)
     }
+
+    pub fn sign<T: Signed>(&self, item: &mut T) -> Result<()> {
+        item.sign(&self.priv_key)
+    }
+
+    pub(crate) fn authorizations(&self) -> &Authorizations {
+        &self.authorizations
+    }
+
+    /// Decrypt the challenge of a request. Only a malformed challenge is
+    /// reported as an error: if decryption fails, a challenge derived from
+    /// the ciphertext with a secret of the server is used instead (implicit
+    /// rejection), so that the response does not tell whether the challenge
+    /// was valid, even when the same ciphertext is sent again. The resulting
+    /// token is then useless to the client.
+    pub(crate) fn decrypt_challenge(&self, challenge: &str) -> Result<Vec<u8>> {
+        let data = base64::decode_block(challenge).map_err(|_| Error::BadChallenge)?;
+        if data.len() != self.priv_key.size() {
+            return Err(Error::BadChallenge);
+        }
+        let mut fallback = self.secret.derive(&data)?;
+        fallback.truncate(TOKEN_SIZE);
+        let mut challenge: Vec<u8> = vec![0; self.priv_key.size()];
+        match self.priv_key.decrypt(&data, &mut challenge) {
+            Ok(size) if size == TOKEN_SIZE => {
+                challenge.truncate(size);
+                Ok(challenge)
+            }
+            Ok(size) => {
+                log::warn!("Rejected challenge: unexpected token size {}", size);
+                Ok(fallback)
+            }
+            Err(e) => {
+                log::warn!("Rejected challenge: {}", e);
+                Ok(fallback)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use std::path::PathBuf;
 
-    fn create_checker() -> Result<Checker> {
+    pub(crate) fn create_checker() -> Result<Checker> {
         let data_dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "..", "tests"].iter().collect();
         let path = data_dir.join("authorizations.toml");
         let authorizations = Authorizations::from_file(path)?;
@@ -162,7 +863,7 @@ mod tests {
         Checker::new(&uri, authorizations)
     }
 
-    fn create_requester() -> Result<Requester> {
+    pub(crate) fn create_requester() -> Result<Requester> {
         let data_dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "..", "tests"].iter().collect();
         let checker_pubkey_path = data_dir.join("server.pubkey.pem");
         let path = data_dir.join("client1.privkey.pem");
@@ -176,9 +877,317 @@ mod tests {
         assert!(checker.is_ok());
         let requester = create_requester();
         assert!(requester.is_ok());
-        let request = requester.unwrap().make("Client 1", "date");
+        let requester = requester.unwrap();
+        let request = requester.make("Client 1", "date");
         assert!(request.is_ok());
-        let authorization = checker.unwrap().check(&request.unwrap());
+        let authorization = checker.unwrap().check(&request.unwrap(), None);
         assert!(authorization.is_ok());
+        assert!(requester.check(&authorization.unwrap()).unwrap());
+    }
+
+    #[test]
+    fn check_tampered_authorization() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 1", "date").unwrap();
+        let mut authorization = checker.check(&request, None).unwrap();
+        authorization.command = "reboot".to_string();
+        assert!(!requester.check(&authorization).unwrap());
+    }
+
+    #[test]
+    fn check_manual_approval() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 1", "poweroff").unwrap();
+        let result = checker.check(&request, None);
+        assert!(matches!(result, Err(Error::ApprovalRequired)));
+    }
+
+    #[test]
+    fn check_denial_reasons() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 3", "date").unwrap();
+        let result = checker.check(&request, None);
+        assert!(matches!(result, Err(Error::UnknownClient(_))));
+        let request = requester.make("Client 1", "reboot").unwrap();
+        let result = checker.check(&request, None);
+        assert!(matches!(result, Err(Error::CommandNotAllowed(_))));
+        let mut request = requester.make("Client 1", "date").unwrap();
+        request.challenge = base64::encode_block(b"garbage");
+        let result = checker.check(&request, None);
+        assert!(matches!(result, Err(Error::BadChallenge)));
+    }
+
+    #[test]
+    fn check_command_pattern() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester
+            .make("Client 1", "/usr/bin/tail -n 20 /var/log/syslog")
+            .unwrap();
+        assert!(checker.check(&request, None).is_ok());
+        for command in [
+            "/usr/bin/tail -n 200 /var/log/syslog",
+            "/usr/bin/tail -n 20 /etc/shadow",
+            "tail -n 20 /var/log/syslog",
+        ] {
+            let request = requester.make("Client 1", command).unwrap();
+            let result = checker.check(&request, None);
+            assert!(matches!(result, Err(Error::CommandNotAllowed(_))));
+        }
+    }
+
+    #[test]
+    fn check_exec_context() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 1", "make install").unwrap();
+        let mut authorization = checker.check(&request, None).unwrap();
+        assert_eq!(authorization.exec.user.as_deref(), Some("nobody"));
+        assert_eq!(authorization.exec.cwd.as_deref(), Some("/tmp"));
+        assert!(requester.check(&authorization).unwrap());
+        authorization.exec.user = Some("root".to_string());
+        assert!(!requester.check(&authorization).unwrap());
+        let request = requester.make("Client 1", "date").unwrap();
+        let authorization = checker.check(&request, None).unwrap();
+        assert!(authorization.exec.is_empty());
+    }
+
+    #[test]
+    fn check_action() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let params = |service: &str| Params::from([("service".to_string(), service.to_string())]);
+        let request = requester
+            .make_action("Client 1", "restart-service", params("nginx"))
+            .unwrap();
+        let mut authorization = checker.check(&request, None).unwrap();
+        assert_eq!(
+            authorization.argv,
+            vec!["/usr/bin/systemctl", "restart", "nginx.service"]
+        );
+        assert!(requester.check(&authorization).unwrap());
+        authorization.argv[2] = "sshd.service".to_string();
+        assert!(!requester.check(&authorization).unwrap());
+        let request = requester
+            .make_action("Client 1", "restart-service", params("sshd"))
+            .unwrap();
+        let result = checker.check(&request, None);
+        assert!(matches!(result, Err(Error::CommandDenied(_))));
+        let request = requester
+            .make_action("Client 1", "restart-service", params("nginx;reboot"))
+            .unwrap();
+        let result = checker.check(&request, None);
+        assert!(matches!(result, Err(Error::InvalidParameter(_))));
+        let request = requester
+            .make("Client 1", "/usr/bin/systemctl restart nginx.service")
+            .unwrap();
+        let result = checker.check(&request, None);
+        assert!(matches!(result, Err(Error::CommandNotAllowed(_))));
+        let request = requester
+            .make_action("Client 1", "date", Params::new())
+            .unwrap();
+        let result = checker.check(&request, None);
+        assert!(matches!(result, Err(Error::CommandNotAllowed(_))));
+    }
+
+    #[test]
+    fn load_fragments() {
+        let data_dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "..", "tests"].iter().collect();
+        let dir = std::env::temp_dir().join(format!("ostiarius-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("keys")).unwrap();
+        std::fs::copy(
+            data_dir.join("server.pubkey.pem"),
+            dir.join("keys/client.pem"),
+        )
+        .unwrap();
+        let write = |name: &str, contents: &str| std::fs::write(dir.join(name), contents).unwrap();
+        write(
+            "10-clients.toml",
+            r#"
+            [[clients]]
+            name = "Client 1"
+            pub_key_file = "keys/client.pem"
+            roles = ["ops"]
+            "#,
+        );
+        write("20-roles.toml", "[roles.ops]\ncommands = [\"uptime\"]\n");
+        write(".30-hidden.toml", "garbage");
+        write("README", "garbage");
+        let authorizations = Authorizations::from_file(&dir).unwrap();
+        let client = &authorizations.clients()[0];
+        assert!(client.pub_key.starts_with("-----BEGIN PUBLIC KEY-----"));
+        assert!(client.is_allowed("uptime"));
+        write("30-roles.toml", "[roles.ops]\ncommands = [\"date\"]\n");
+        let result = Authorizations::from_file(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        match result {
+            Err(Error::InFile(path, e)) => {
+                assert!(path.ends_with("30-roles.toml"));
+                assert!(matches!(*e, Error::InvalidPolicy(_)));
+            }
+            _ => panic!("duplicate role not detected"),
+        }
+    }
+
+    #[test]
+    fn replace_authorizations() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let data_dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "..", "tests"].iter().collect();
+        let contents = std::fs::read_to_string(data_dir.join("authorizations.toml")).unwrap();
+        let contents = contents.replacen("\"date\",", "", 1);
+        let authorizations: Authorizations = toml::from_str(&contents).unwrap();
+        let reloaded = checker.with_authorizations(authorizations);
+        let request = requester.make("Client 1", "date").unwrap();
+        assert!(checker.check(&request, None).is_ok());
+        let result = reloaded.check(&request, None);
+        assert!(matches!(result, Err(Error::CommandNotAllowed(_))));
+        let contents = contents.replacen("MIICIjANBgkq", "garbage", 1);
+        let result = toml::from_str::<Authorizations>(&contents);
+        assert!(result.unwrap_err().to_string().contains("'Client 1'"));
+    }
+
+    #[test]
+    fn check_deny_rules() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester
+            .make("Client 1", "/usr/sbin/service nginx stop")
+            .unwrap();
+        assert!(checker.check(&request, None).is_ok());
+        for command in [
+            "/usr/sbin/service sshd stop",
+            "/usr/sbin/service nginx poweroff",
+        ] {
+            let request = requester.make("Client 1", command).unwrap();
+            let result = checker.check(&request, None);
+            assert!(matches!(result, Err(Error::CommandDenied(_))));
+        }
+        let commands = vec![
+            "/usr/sbin/service sshd start".to_string(),
+            "/usr/sbin/service sshd stop".to_string(),
+        ];
+        let request = requester.make_batch("Client 1", &commands).unwrap();
+        let authorization = checker.check_batch(&request, None).unwrap();
+        assert!(authorization.decisions[0].authorized);
+        assert!(!authorization.decisions[1].authorized);
+    }
+
+    #[test]
+    fn check_source_address() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 1", "uptime").unwrap();
+        let source = "10.1.2.3".parse().ok();
+        let authorization = checker.check(&request, source).unwrap();
+        assert_eq!(authorization.source, source);
+        assert!(requester.check(&authorization).unwrap());
+        let result = checker.check(&request, "192.168.1.2".parse().ok());
+        assert!(matches!(result, Err(Error::CommandNotAllowed(_))));
+        let request = requester.make("Client 1", "date").unwrap();
+        assert!(checker.check(&request, "192.168.1.2".parse().ok()).is_ok());
+        let request = requester.make("Client 2", "uname -a").unwrap();
+        let result = checker.check(&request, "192.168.1.2".parse().ok());
+        assert!(matches!(result, Err(Error::SourceNotAllowed(_))));
+        let result = checker.check(&request, None);
+        assert!(matches!(result, Err(Error::SourceNotAllowed(_))));
+    }
+
+    #[derive(Debug)]
+    struct FixedClock(DateTime<Utc>);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> DateTime<Utc> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn check_time_window() {
+        let saturday = DateTime::parse_from_rfc3339("2022-12-17T12:00:00+01:00").unwrap();
+        let monday = DateTime::parse_from_rfc3339("2022-12-19T12:00:00+01:00").unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 1", "apt-get upgrade").unwrap();
+        let checker = create_checker()
+            .unwrap()
+            .with_clock(Arc::new(FixedClock(saturday.into())));
+        let authorization = checker.check(&request, None).unwrap();
+        assert_eq!(authorization.timestamp, saturday);
+        let checker = checker.with_clock(Arc::new(FixedClock(monday.into())));
+        let result = checker.check(&request, None);
+        let next = DateTime::parse_from_rfc3339("2022-12-24T00:00:00+01:00").unwrap();
+        assert!(matches!(result, Err(Error::OutsideTimeWindow(Some(t))) if t == next));
+    }
+
+    #[test]
+    fn check_undecryptable_challenge() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let mut request = requester.make("Client 1", "date").unwrap();
+        let mut challenge = base64::decode_block(&request.challenge).unwrap();
+        challenge[0] ^= 0xff;
+        request.challenge = base64::encode_block(&challenge);
+        let authorization = checker.check(&request, None).unwrap();
+        assert!(!requester.check(&authorization).unwrap());
+        let mut request = requester.make("Client 1", "reboot").unwrap();
+        request.challenge = base64::encode_block(&challenge);
+        let result = checker.check(&request, None);
+        assert!(matches!(result, Err(Error::CommandNotAllowed(_))));
+    }
+
+    #[test]
+    fn check_pkcs1_challenge() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let mut request = requester.make("Client 1", "date").unwrap();
+        let pub_key = checker.public_key().unwrap();
+        let mut challenge = vec![0; pub_key.size() as usize];
+        pub_key
+            .public_encrypt(requester.token(), &mut challenge, Padding::PKCS1)
+            .unwrap();
+        request.challenge = base64::encode_block(&challenge);
+        let authorization = checker.check(&request, None).unwrap();
+        assert!(!requester.check(&authorization).unwrap());
+    }
+
+    #[test]
+    fn check_same_bad_challenge() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let mut request = requester.make("Client 1", "date").unwrap();
+        let mut challenge = base64::decode_block(&request.challenge).unwrap();
+        challenge[0] ^= 0x01;
+        request.challenge = base64::encode_block(&challenge);
+        let token = |checker: &Checker| {
+            let authorization = checker.check(&request, None).unwrap();
+            let data = base64::decode_block(&authorization.token).unwrap();
+            let mut token = vec![0; requester.priv_key.size()];
+            let size = requester.priv_key.decrypt(&data, &mut token).unwrap();
+            token.truncate(size);
+            token
+        };
+        let first = token(&checker);
+        assert_ne!(&first[..], &requester.token()[..]);
+        assert_eq!(token(&checker), first);
+        let reloaded = checker.with_authorizations(checker.authorizations().clone());
+        assert_eq!(token(&reloaded), first);
+        let other = create_checker().unwrap();
+        assert_ne!(token(&other), first);
+    }
+
+    #[test]
+    fn checker_public_key() {
+        let checker = create_checker().unwrap();
+        let data_dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "..", "tests"].iter().collect();
+        let pem = std::fs::read(data_dir.join("server.pubkey.pem")).unwrap();
+        let expected = Rsa::public_key_from_pem(&pem).unwrap();
+        let actual = checker.public_key().unwrap();
+        assert_eq!(
+            actual.public_key_to_der().unwrap(),
+            expected.public_key_to_der().unwrap()
+        );
     }
 }
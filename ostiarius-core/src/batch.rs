@@ -0,0 +1,192 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::exec::ExecContext;
+use crate::{ApprovalMode, Authorization, Checker, Requester, Result, Signed};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BatchRequest {
+    pub name: String,
+    pub commands: Vec<String>,
+    #[serde(with = "crate::wire::binary")]
+    pub challenge: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Decision {
+    pub command: String,
+    pub authorized: bool,
+    pub id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "ExecContext::is_empty")]
+    pub exec: ExecContext,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchAuthorization {
+    pub id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub name: String,
+    pub decisions: Vec<Decision>,
+    #[serde(with = "crate::wire::binary")]
+    pub token: String,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::wire::address"
+    )]
+    pub source: Option<IpAddr>,
+    #[serde(default, with = "crate::wire::binary")]
+    pub signature: String,
+}
+
+impl Signed for BatchAuthorization {
+    fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    fn signature_mut(&mut self) -> &mut String {
+        &mut self.signature
+    }
+}
+
+impl Requester {
+    pub fn make_batch(&self, name: &str, commands: &[String]) -> Result<BatchRequest> {
+        let request = BatchRequest {
+            name: name.to_string(),
+            commands: commands.to_vec(),
+            challenge: self.make_challenge()?,
+        };
+        Ok(request)
+    }
+
+    pub fn check_batch(&self, authorization: &BatchAuthorization) -> Result<bool> {
+        Ok(self.verify(authorization)? && self.check_token(&authorization.token)?)
+    }
+}
+
+impl Checker {
+    pub fn check_batch(
+        &self,
+        request: &BatchRequest,
+        source: Option<IpAddr>,
+    ) -> Result<BatchAuthorization> {
+        let challenge = self.decrypt_challenge(&request.challenge)?;
+        let clients = self.find_clients(&request.name, source)?;
+        let client = clients[0];
+        let now = self.now();
+        let decisions = request
+            .commands
+            .iter()
+            .map(|command| {
+                let denied = clients.iter().any(|client| client.denies(command));
+                let rule = clients
+                    .iter()
+                    .filter_map(|client| client.rule(command))
+                    .find(|rule| {
+                        rule.approval == ApprovalMode::Auto && rule.check_schedule(now).is_ok()
+                    })
+                    .filter(|_| !denied);
+                Decision {
+                    command: command.clone(),
+                    authorized: rule.is_some(),
+                    id: rule.map(|_| Uuid::new_v4()),
+                    exec: rule.map(|rule| rule.exec.clone()).unwrap_or_default(),
+                }
+            })
+            .collect();
+        let mut authorization = BatchAuthorization {
+            id: Uuid::new_v4(),
+            timestamp: self.now(),
+            name: request.name.clone(),
+            decisions,
+            token: client.encrypt(&challenge)?,
+            source,
+            signature: String::new(),
+        };
+        self.sign(&mut authorization)?;
+        Ok(authorization)
+    }
+
+    /// Get an authorization for each authorized command of a batch, signed as
+    /// if it was granted alone, so that it can be served and checked like any
+    /// other authorization.
+    pub fn split_batch(&self, batch: &BatchAuthorization) -> Result<Vec<Authorization>> {
+        batch
+            .decisions
+            .iter()
+            .filter_map(|decision| decision.id.map(|id| (id, decision)))
+            .map(|(id, decision)| {
+                let mut authorization = Authorization {
+                    id,
+                    timestamp: batch.timestamp,
+                    name: batch.name.clone(),
+                    command: decision.command.clone(),
+                    params: None,
+                    argv: Vec::new(),
+                    token: batch.token.clone(),
+                    source: batch.source,
+                    exec: decision.exec.clone(),
+                    approvals: Vec::new(),
+                    signature: String::new(),
+                };
+                self.sign(&mut authorization)?;
+                Ok(authorization)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::authorization::tests::{create_checker, create_requester};
+    use crate::Error;
+
+    #[test]
+    fn make_and_check_batch() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let commands = vec!["date".to_string(), "uname -a".to_string()];
+        let request = requester.make_batch("Client 1", &commands).unwrap();
+        let authorization = checker.check_batch(&request, None).unwrap();
+        assert!(requester.check_batch(&authorization).unwrap());
+        assert!(authorization.decisions[0].authorized);
+        assert!(!authorization.decisions[1].authorized);
+        let authorizations = checker.split_batch(&authorization).unwrap();
+        assert_eq!(authorizations.len(), 1);
+        assert_eq!(authorizations[0].command, "date");
+        assert_eq!(Some(authorizations[0].id), authorization.decisions[0].id);
+        assert!(requester.check(&authorizations[0]).unwrap());
+    }
+
+    #[test]
+    fn check_tampered_batch() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let commands = vec!["date".to_string(), "uname -a".to_string()];
+        let request = requester.make_batch("Client 1", &commands).unwrap();
+        let authorization = checker.check_batch(&request, None).unwrap();
+        let mut tampered = authorization.clone();
+        tampered.decisions[1].authorized = true;
+        assert!(!requester.check_batch(&tampered).unwrap());
+        let mut tampered = authorization;
+        tampered.decisions[0].command = "reboot".to_string();
+        assert!(!requester.check_batch(&tampered).unwrap());
+    }
+
+    #[test]
+    fn check_batch_unknown_client() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let commands = vec!["date".to_string()];
+        let request = requester.make_batch("Client 3", &commands).unwrap();
+        let result = checker.check_batch(&request, None);
+        assert!(matches!(result, Err(Error::UnknownClient(_))));
+    }
+}
@@ -0,0 +1,176 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Credentials of operators and clients, for requests to the API which carry
+//! no signed body, e.g. to list or revoke sessions.
+//!
+//! A credential is the JSON encoding of a `Credential`, signed with the key of
+//! its owner and base64-encoded, given in the `Authorization` header of the
+//! request with the `Ostiarius` scheme. It is bound to the method and the path
+//! of the request, and only valid within `MAX_CLOCK_SKEW` of its timestamp.
+
+use crate::{Checker, Error, Operator, Result, Signed};
+use chrono::{DateTime, Utc};
+use openssl::base64;
+use serde::{Deserialize, Serialize};
+
+/// Scheme of the `Authorization` header carrying a credential.
+pub const SCHEME: &str = "Ostiarius";
+
+/// Maximum difference between the timestamp of a signed request and the time
+/// of the server, in seconds.
+pub const MAX_CLOCK_SKEW: i64 = 300;
+
+/// Check that a signed request was made recently, so that it can not be
+/// replayed long after it was captured.
+pub(crate) fn check_timestamp(timestamp: DateTime<Utc>, now: DateTime<Utc>) -> Result<()> {
+    if (now - timestamp).num_seconds().abs() > MAX_CLOCK_SKEW {
+        return Err(Error::Unauthorized);
+    }
+    Ok(())
+}
+
+/// Proof that `name` sent a request with the given method and path.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Credential {
+    pub name: String,
+    pub method: String,
+    pub path: String,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub signature: String,
+}
+
+impl Signed for Credential {
+    fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    fn signature_mut(&mut self) -> &mut String {
+        &mut self.signature
+    }
+}
+
+impl Credential {
+    pub fn new(name: &str, method: &str, path: &str) -> Self {
+        Credential {
+            name: name.to_string(),
+            method: method.to_ascii_uppercase(),
+            path: path.to_string(),
+            timestamp: chrono::offset::Utc::now(),
+            signature: String::new(),
+        }
+    }
+
+    /// Encode the credential as the value of an `Authorization` header.
+    pub fn to_header(&self) -> Result<String> {
+        let data = serde_json::to_vec(self)?;
+        Ok(format!("{} {}", SCHEME, base64::encode_block(&data)))
+    }
+
+    /// Decode a credential from the value of an `Authorization` header.
+    pub fn from_header(value: &str) -> Result<Self> {
+        let encoded = value
+            .strip_prefix(SCHEME)
+            .and_then(|value| value.strip_prefix(' '))
+            .ok_or(Error::Unauthorized)?;
+        let data = base64::decode_block(encoded.trim()).map_err(|_| Error::Unauthorized)?;
+        serde_json::from_slice(&data).map_err(|_| Error::Unauthorized)
+    }
+
+    fn check_request(&self, method: &str, path: &str, now: DateTime<Utc>) -> Result<()> {
+        if !self.method.eq_ignore_ascii_case(method) || self.path != path {
+            return Err(Error::Unauthorized);
+        }
+        check_timestamp(self.timestamp, now)
+    }
+}
+
+impl Checker {
+    /// Check that a request with the given method and path was sent by an
+    /// operator.
+    pub fn verify_operator(
+        &self,
+        credential: &Credential,
+        method: &str,
+        path: &str,
+    ) -> Result<&Operator> {
+        credential.check_request(method, path, self.now())?;
+        let operator = self
+            .authorizations()
+            .operators()
+            .iter()
+            .find(|operator| operator.name == credential.name)
+            .ok_or(Error::Unauthorized)?;
+        if !credential.verify(&operator.public_key()?)? {
+            return Err(Error::Unauthorized);
+        }
+        Ok(operator)
+    }
+
+    /// Check that a request with the given method and path was sent by a
+    /// client, whatever its source address.
+    pub fn verify_client(&self, credential: &Credential, method: &str, path: &str) -> Result<()> {
+        credential.check_request(method, path, self.now())?;
+        for client in self
+            .authorizations()
+            .clients()
+            .iter()
+            .filter(|client| client.name == credential.name)
+        {
+            let pub_key = openssl::rsa::Rsa::public_key_from_pem(client.pub_key.as_bytes())?;
+            if credential.verify(&pub_key)? {
+                return Ok(());
+            }
+        }
+        Err(Error::Unauthorized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authorization::tests::create_checker;
+    use crate::RsaPrivateKey;
+    use chrono::Duration;
+    use std::path::PathBuf;
+
+    fn key(name: &str) -> RsaPrivateKey {
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "..", "tests", name]
+            .iter()
+            .collect();
+        RsaPrivateKey::from_uri(&format!("file://{}", path.display())).unwrap()
+    }
+
+    #[test]
+    fn verify_credentials() {
+        let checker = create_checker().unwrap();
+        let path = "/api/v1/sessions";
+        let mut credential = Credential::new("Operator 1", "get", path);
+        credential.sign(&key("operator1.privkey.pem")).unwrap();
+        let header = credential.to_header().unwrap();
+        let credential = Credential::from_header(&header).unwrap();
+        let operator = checker.verify_operator(&credential, "GET", path).unwrap();
+        assert_eq!(operator.name, "Operator 1");
+        for (method, path) in [("DELETE", path), ("GET", "/api/v1/approvals")] {
+            let result = checker.verify_operator(&credential, method, path);
+            assert!(matches!(result, Err(Error::Unauthorized)));
+        }
+        assert!(checker.verify_client(&credential, "GET", path).is_err());
+        let mut stale = credential;
+        stale.timestamp -= Duration::seconds(MAX_CLOCK_SKEW + 1);
+        stale.sign(&key("operator1.privkey.pem")).unwrap();
+        let result = checker.verify_operator(&stale, "GET", path);
+        assert!(matches!(result, Err(Error::Unauthorized)));
+        let mut credential = Credential::new("Client 1", "DELETE", path);
+        credential.sign(&key("client1.privkey.pem")).unwrap();
+        assert!(checker.verify_client(&credential, "DELETE", path).is_ok());
+        assert!(checker
+            .verify_operator(&credential, "DELETE", path)
+            .is_err());
+        assert!(Credential::from_header("Basic Zm9vOmJhcg==").is_err());
+    }
+}
@@ -4,10 +4,14 @@
 // SPDX-License-Identifier: MIT
 //
 
+mod fingerprint;
 mod key;
 mod openssl;
 pub mod password;
 #[cfg(feature = "pkcs11")]
 mod pkcs11;
+mod signature;
 
+pub use fingerprint::{fingerprint, normalize_fingerprint};
 pub use key::{PrivateKey, RsaPrivateKey};
+pub use signature::Signed;
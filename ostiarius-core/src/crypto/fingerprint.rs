@@ -0,0 +1,61 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::Result;
+use openssl::{base64, hash::MessageDigest, pkey::Public, rsa::Rsa};
+
+/// Compute the fingerprint of a public key: the SHA-256 digest of its
+/// DER-encoded SubjectPublicKeyInfo, base64-encoded without padding and
+/// prefixed with `SHA256:`.
+pub fn fingerprint(key: &Rsa<Public>) -> Result<String> {
+    let der = key.public_key_to_der()?;
+    let digest = openssl::hash::hash(MessageDigest::sha256(), &der)?;
+    let encoded = base64::encode_block(&digest);
+    Ok(format!("SHA256:{}", encoded.trim_end_matches('=')))
+}
+
+/// Normalize a fingerprint given by a user, which may lack the `SHA256:`
+/// prefix or have base64 padding, as printed by `openssl dgst | base64`.
+pub fn normalize_fingerprint(fingerprint: &str) -> String {
+    let digest = fingerprint.trim();
+    let digest = digest.strip_prefix("SHA256:").unwrap_or(digest);
+    format!("SHA256:{}", digest.trim_end_matches('='))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const SERVER_FINGERPRINT: &str = "SHA256:sZTUV/a7ewpKEHyra7eh2OtkDJvF/8h0eXRvlkR9W+U";
+
+    #[test]
+    fn fingerprint_format() {
+        let path: PathBuf = [
+            env!("CARGO_MANIFEST_DIR"),
+            "..",
+            "tests",
+            "server.pubkey.pem",
+        ]
+        .iter()
+        .collect();
+        let pem = std::fs::read(path).unwrap();
+        let key = Rsa::public_key_from_pem(&pem).unwrap();
+        assert_eq!(fingerprint(&key).unwrap(), SERVER_FINGERPRINT);
+    }
+
+    #[test]
+    fn normalize_user_fingerprint() {
+        for given in [
+            "sZTUV/a7ewpKEHyra7eh2OtkDJvF/8h0eXRvlkR9W+U=",
+            "sZTUV/a7ewpKEHyra7eh2OtkDJvF/8h0eXRvlkR9W+U",
+            "SHA256:sZTUV/a7ewpKEHyra7eh2OtkDJvF/8h0eXRvlkR9W+U",
+            " SHA256:sZTUV/a7ewpKEHyra7eh2OtkDJvF/8h0eXRvlkR9W+U=\n",
+        ] {
+            assert_eq!(normalize_fingerprint(given), SERVER_FINGERPRINT);
+        }
+    }
+}
@@ -7,11 +7,18 @@
 #[cfg(feature = "pkcs11")]
 use crate::crypto::pkcs11::Pkcs11RsaPrivateKey;
 use crate::{crypto::openssl::FileRsaPrivateKey, Error, Result};
+use openssl::{pkey::Public, rsa::Rsa};
 use url::Url;
 
+/// RSA private key, used both to decrypt data encrypted with RSA-OAEP and to
+/// sign data. Decryption must never use PKCS#1 v1.5 padding: the ciphertexts
+/// are chosen by clients, and a padding oracle would let them forge
+/// signatures.
 pub trait PrivateKey {
     fn decrypt(&self, from: &[u8], to: &mut [u8]) -> Result<usize>;
     fn size(&self) -> usize;
+    fn public_key(&self) -> Result<Rsa<Public>>;
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>>;
 }
 
 #[derive(Debug, Clone)]
@@ -55,4 +62,18 @@ impl PrivateKey for RsaPrivateKey {
             RsaPrivateKey::Pkcs11(key) => key.size(),
         }
     }
+    fn public_key(&self) -> Result<Rsa<Public>> {
+        match self {
+            RsaPrivateKey::File(key) => key.public_key(),
+            #[cfg(feature = "pkcs11")]
+            RsaPrivateKey::Pkcs11(key) => key.public_key(),
+        }
+    }
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            RsaPrivateKey::File(key) => key.sign(data),
+            #[cfg(feature = "pkcs11")]
+            RsaPrivateKey::Pkcs11(key) => key.sign(data),
+        }
+    }
 }
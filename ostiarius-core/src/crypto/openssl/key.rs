@@ -6,8 +6,10 @@
 
 use crate::{crypto::PrivateKey, Error, Result};
 use openssl::{
-    pkey::Private,
+    hash::MessageDigest,
+    pkey::{PKey, Private, Public},
     rsa::{Padding, Rsa},
+    sign::Signer,
 };
 use url::Url;
 
@@ -35,10 +37,22 @@ impl FileRsaPrivateKey {
 
 impl PrivateKey for FileRsaPrivateKey {
     fn decrypt(&self, from: &[u8], to: &mut [u8]) -> Result<usize> {
-        let size = self.inner.private_decrypt(from, to, Padding::PKCS1)?;
+        let size = self.inner.private_decrypt(from, to, Padding::PKCS1_OAEP)?;
         Ok(size)
     }
     fn size(&self) -> usize {
         self.inner.size() as usize
     }
+    fn public_key(&self) -> Result<Rsa<Public>> {
+        let n = self.inner.n().to_owned()?;
+        let e = self.inner.e().to_owned()?;
+        let key = Rsa::from_public_components(n, e)?;
+        Ok(key)
+    }
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let pkey = PKey::from_rsa(self.inner.clone())?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &pkey)?;
+        signer.update(data)?;
+        Ok(signer.sign_to_vec()?)
+    }
 }
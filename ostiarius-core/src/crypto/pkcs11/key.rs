@@ -9,10 +9,14 @@ use crate::{crypto::PrivateKey, Error, Result};
 
 use cryptoki::{
     context::{CInitializeArgs, Pkcs11},
-    mechanism::Mechanism,
+    mechanism::{
+        rsa::{PkcsMgfType, PkcsOaepParams, PkcsOaepSourceType},
+        Mechanism, MechanismType,
+    },
     object::{Attribute, AttributeType, ObjectHandle},
     session::{Session, SessionFlags, UserType},
 };
+use openssl::{bn::BigNum, pkey::Public, rsa::Rsa};
 use url::Url;
 
 #[derive(Debug, Clone)]
@@ -77,7 +81,15 @@ impl Pkcs11RsaPrivateKey {
 impl PrivateKey for Pkcs11RsaPrivateKey {
     fn decrypt(&self, from: &[u8], to: &mut [u8]) -> Result<usize> {
         let (session, key) = Self::acquire(&self.pkcs11, &self.url)?;
-        let data = session.decrypt(&Mechanism::RsaPkcs, key, from)?;
+        // Same parameters as the OAEP padding of OpenSSL.
+        let params = PkcsOaepParams {
+            hash_alg: MechanismType::SHA1,
+            mgf: PkcsMgfType::MGF1_SHA1,
+            source: PkcsOaepSourceType::DATA_SPECIFIED,
+            source_data: std::ptr::null(),
+            source_data_len: 0.into(),
+        };
+        let data = session.decrypt(&Mechanism::RsaPkcsOaep(params), key, from)?;
         let limit = std::cmp::min(to.len(), data.len());
         to[..limit].copy_from_slice(&data[..limit]);
         Ok(limit)
@@ -86,6 +98,31 @@ impl PrivateKey for Pkcs11RsaPrivateKey {
     fn size(&self) -> usize {
         self.size
     }
+
+    fn public_key(&self) -> Result<Rsa<Public>> {
+        let (session, key) = Self::acquire(&self.pkcs11, &self.url)?;
+        let attr_types = vec![AttributeType::Modulus, AttributeType::PublicExponent];
+        let attrs = session.get_attributes(key, &attr_types)?;
+        let mut n = None;
+        let mut e = None;
+        for attr in attrs {
+            match attr {
+                Attribute::Modulus(modulus) => n = Some(BigNum::from_slice(&modulus)?),
+                Attribute::PublicExponent(exponent) => e = Some(BigNum::from_slice(&exponent)?),
+                _ => {}
+            }
+        }
+        match (n, e) {
+            (Some(n), Some(e)) => Ok(Rsa::from_public_components(n, e)?),
+            _ => Err(Error::InvalidKey("No public components".to_string())),
+        }
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let (session, key) = Self::acquire(&self.pkcs11, &self.url)?;
+        let signature = session.sign(&Mechanism::Sha256RsaPkcs, key, data)?;
+        Ok(signature)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,45 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::{PrivateKey, Result};
+use openssl::{
+    base64,
+    hash::MessageDigest,
+    pkey::{PKey, Public},
+    rsa::Rsa,
+    sign::Verifier,
+};
+use serde::Serialize;
+
+/// Item carrying a RSA signature (SHA-256, PKCS#1 v1.5) of its JSON
+/// serialization, computed with an empty signature field.
+pub trait Signed: Serialize + Clone {
+    fn signature(&self) -> &str;
+    fn signature_mut(&mut self) -> &mut String;
+
+    fn signed_data(&self) -> Result<Vec<u8>> {
+        let mut unsigned = self.clone();
+        unsigned.signature_mut().clear();
+        Ok(serde_json::to_vec(&unsigned)?)
+    }
+
+    fn sign<K: PrivateKey>(&mut self, key: &K) -> Result<()> {
+        let signature = key.sign(&self.signed_data()?)?;
+        *self.signature_mut() = base64::encode_block(&signature);
+        Ok(())
+    }
+
+    fn verify(&self, pub_key: &Rsa<Public>) -> Result<bool> {
+        if self.signature().is_empty() {
+            return Ok(false);
+        }
+        let signature = base64::decode_block(self.signature())?;
+        let pkey = PKey::from_rsa(pub_key.clone())?;
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &pkey)?;
+        verifier.update(&self.signed_data()?)?;
+        Ok(verifier.verify(&signature).unwrap_or(false))
+    }
+}
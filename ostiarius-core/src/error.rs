@@ -17,14 +17,52 @@ pub enum Error {
     OpenSsl(#[from] openssl::error::ErrorStack),
     #[error("Integer parsing error: {0}")]
     ParseInt(#[from] core::num::ParseIntError),
+    #[error("JSON serialization error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("CBOR serialization error: {0}")]
+    Cbor(String),
     #[error("TOML deserialization error: {0}")]
     Toml(#[from] toml::de::Error),
+    #[error("YAML deserialization error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
     #[error("URL parsing error: {0}")]
     Url(#[from] url::ParseError),
     #[error("Environment error: {0}")]
     Env(#[from] std::env::VarError),
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("Unknown client: {0}")]
+    UnknownClient(String),
+    #[error("Command not allowed: {0}")]
+    CommandNotAllowed(String),
+    #[error("Command denied: {0}")]
+    CommandDenied(String),
+    #[error("Source address not allowed: {0}")]
+    SourceNotAllowed(String),
+    #[error(
+        "Outside time window{}",
+        .0.map(|t| format!(", next allowed at {}", t)).unwrap_or_default()
+    )]
+    OutsideTimeWindow(Option<chrono::DateTime<chrono::Utc>>),
+    #[error("Session not allowed: {0}")]
+    SessionNotAllowed(String),
+    #[error("Bad challenge")]
+    BadChallenge,
+    #[error(
+        "Rate limited{}",
+        .0.map(|t| format!(", retry at {}", t)).unwrap_or_default()
+    )]
+    RateLimited(Option<chrono::DateTime<chrono::Utc>>),
+    #[error("Approval required")]
+    ApprovalRequired,
+    #[error("Duplicate approval by operator '{0}'")]
+    DuplicateApproval(String),
+    #[error("Invalid parameter: {0}")]
+    InvalidParameter(String),
+    #[error("Invalid policy: {0}")]
+    InvalidPolicy(String),
+    #[error("{}: {1}", .0.display())]
+    InFile(std::path::PathBuf, Box<Error>),
     #[error("Invalid path: {0:?}")]
     InvalidPath(std::ffi::OsString),
     #[error("Invalid URI: {0}")]
@@ -36,6 +74,16 @@ pub enum Error {
     InvalidKey(String),
     #[error("Invalid provider: {0}")]
     InvalidProvider(String),
+    #[error("Invalid known server entry at line {0}")]
+    InvalidKnownServer(usize),
+    #[error("Invalid armored data: {0}")]
+    InvalidArmor(String),
+    #[error("Invalid frame: {0}")]
+    InvalidFrame(String),
+    #[error("Invalid baud rate: {0}")]
+    InvalidBaudRate(u32),
+    #[error("Transport error: {0}")]
+    Transport(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
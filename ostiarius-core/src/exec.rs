@@ -0,0 +1,253 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Context in which the client executes an authorized command.
+
+use crate::utils::glob_match;
+use crate::{Error, Result};
+use openssl::sha::Sha256;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Environment of the command. If `allow` is given, only the matching
+/// variables are inherited from the client. The `strip` variables are then
+/// removed, and the `set` ones added.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EnvPolicy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub strip: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, String>,
+}
+
+impl EnvPolicy {
+    pub fn is_empty(&self) -> bool {
+        *self == EnvPolicy::default()
+    }
+
+    /// Compute the environment of the command from the one of the client.
+    pub fn apply<I: IntoIterator<Item = (String, String)>>(
+        &self,
+        vars: I,
+    ) -> BTreeMap<String, String> {
+        let matches = |patterns: &[String], name: &str| {
+            patterns.iter().any(|pattern| glob_match(pattern, name))
+        };
+        let mut env = vars
+            .into_iter()
+            .filter(|(name, _)| match &self.allow {
+                Some(allow) => matches(allow, name),
+                None => true,
+            })
+            .filter(|(name, _)| !matches(&self.strip, name))
+            .collect::<BTreeMap<_, _>>();
+        env.extend(self.set.clone());
+        env
+    }
+}
+
+/// Script run by the client with an `interpreter` instead of the program of the
+/// command, which gives it its arguments. Its `body` is given inline, or read
+/// from a `file` relative to the file declaring it. Its `sha256` digest is
+/// computed by the server.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Script {
+    pub interpreter: String,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default, skip_serializing)]
+    pub(crate) file: Option<PathBuf>,
+    #[serde(default)]
+    pub sha256: String,
+}
+
+impl Script {
+    /// Read the body from its file, relative to `base`, and compute its
+    /// digest.
+    pub(crate) fn load(&mut self, base: &Path) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            if !self.body.is_empty() {
+                let message = "both body and file given for script".to_string();
+                return Err(Error::InvalidPolicy(message));
+            }
+            self.body = std::fs::read_to_string(base.join(file))?;
+        }
+        if self.body.is_empty() {
+            return Err(Error::InvalidPolicy("empty script".to_string()));
+        }
+        self.sha256 = digest(self.body.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// User, group, working directory and environment of the command. With an
+/// `executable`, the command is run from this absolute path instead of looking
+/// up its program in `PATH`, after checking the `sha256` digest of the file if
+/// given. With a `script`, the script is run instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ExecContext {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(default, skip_serializing_if = "EnvPolicy::is_empty")]
+    pub env: EnvPolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executable: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<Script>,
+}
+
+impl ExecContext {
+    pub fn is_empty(&self) -> bool {
+        *self == ExecContext::default()
+    }
+
+    /// Check that the executable and the interpreter of the script are
+    /// absolute paths, and that the digest of the executable is made of 64
+    /// hexadecimal digits.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        match &self.script {
+            Some(_) if self.executable.is_some() => {
+                return Err("both executable and script given".into())
+            }
+            Some(script) if !Path::new(&script.interpreter).is_absolute() => {
+                let message = format!(
+                    "interpreter '{}' is not an absolute path",
+                    script.interpreter
+                );
+                return Err(message);
+            }
+            _ => {}
+        }
+        match &self.executable {
+            Some(executable) if !Path::new(executable).is_absolute() => {
+                return Err(format!(
+                    "executable '{}' is not an absolute path",
+                    executable
+                ))
+            }
+            None if self.sha256.is_some() => return Err("sha256 given without executable".into()),
+            _ => {}
+        }
+        match &self.sha256 {
+            Some(digest)
+                if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) =>
+            {
+                Err(format!("invalid SHA-256 digest '{}'", digest))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Compute the SHA-256 digest of some contents, in lowercase hexadecimal.
+pub fn digest<R: Read>(mut reader: R) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        match reader.read(&mut buffer)? {
+            0 => break,
+            size => hasher.update(&buffer[..size]),
+        }
+    }
+    let digest = hasher
+        .finish()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_env_policy() {
+        let policy: EnvPolicy = toml::from_str(
+            r#"
+            allow = ["PATH", "LC_*", "SECRET_TOKEN"]
+            strip = ["SECRET_*"]
+            set = { LANG = "C" }
+            "#,
+        )
+        .unwrap();
+        let vars = [
+            ("PATH", "/usr/bin"),
+            ("LC_ALL", "C"),
+            ("HOME", "/root"),
+            ("SECRET_TOKEN", "1234"),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        let env = policy.apply(vars);
+        let names = env.keys().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(names, vec!["LANG", "LC_ALL", "PATH"]);
+        assert!(EnvPolicy::default().apply(env.clone()) == env);
+    }
+
+    #[test]
+    fn pinned_executable() {
+        let empty = digest(&b""[..]).unwrap();
+        assert_eq!(
+            empty,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        let context = ExecContext {
+            executable: Some("/usr/bin/systemctl".to_string()),
+            sha256: Some(empty.clone()),
+            ..ExecContext::default()
+        };
+        assert!(context.validate().is_ok());
+        for (executable, sha256) in [
+            (Some("systemctl"), None),
+            (None, Some(empty.as_str())),
+            (Some("/usr/bin/systemctl"), Some("e3b0c442")),
+            (Some("/usr/bin/systemctl"), Some(&"z".repeat(64))),
+        ] {
+            let context = ExecContext {
+                executable: executable.map(String::from),
+                sha256: sha256.map(String::from),
+                ..ExecContext::default()
+            };
+            assert!(context.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn load_script() {
+        let mut script: Script = toml::from_str(
+            r#"
+            interpreter = "/bin/sh"
+            body = "echo $1"
+            "#,
+        )
+        .unwrap();
+        script.load(Path::new("")).unwrap();
+        assert_eq!(script.sha256, digest(&b"echo $1"[..]).unwrap());
+        let mut context = ExecContext {
+            script: Some(script.clone()),
+            ..ExecContext::default()
+        };
+        assert!(context.validate().is_ok());
+        context.executable = Some("/bin/sh".to_string());
+        assert!(context.validate().is_err());
+        script.file = Some(PathBuf::from("cleanup.sh"));
+        assert!(script.load(Path::new("")).is_err());
+        script.body.clear();
+        script.file = None;
+        assert!(script.load(Path::new("")).is_err());
+    }
+}
@@ -0,0 +1,354 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Explanation of the decision about a command, without any cryptography.
+//!
+//! The evaluation follows the one of a real request: the declarations of the
+//! client are filtered by source address, the deny patterns are checked, then
+//! the first matching rule is looked for and its schedule enforced. Rate limits
+//! and quotas are not taken into account, nor the rules allowing actions.
+
+use crate::credential::check_timestamp;
+use crate::exec::ExecContext;
+use crate::{ApprovalMode, Authorizations, Checker, Error, Result, Signed};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verdict {
+    Allowed,
+    ApprovalRequired,
+    Denied,
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verdict::Allowed => write!(f, "allowed"),
+            Verdict::ApprovalRequired => write!(f, "approval required"),
+            Verdict::Denied => write!(f, "denied"),
+        }
+    }
+}
+
+/// Rule evaluated for the command, and whether it matched.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Step {
+    pub rule: String,
+    pub matched: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Decision about a command, with the rules which led to it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Explanation {
+    pub name: String,
+    pub command: String,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::wire::address"
+    )]
+    pub source: Option<IpAddr>,
+    pub time: DateTime<Utc>,
+    pub verdict: Verdict,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "ExecContext::is_empty")]
+    pub exec: ExecContext,
+    pub steps: Vec<Step>,
+}
+
+impl std::fmt::Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Client '{}' running `{}`", self.name, self.command)?;
+        if let Some(source) = self.source {
+            write!(f, " from {}", source)?;
+        }
+        write!(f, " at {}: {}", self.time, self.verdict)?;
+        if let Some(reason) = &self.reason {
+            write!(f, " ({})", reason)?;
+        }
+        for step in &self.steps {
+            let outcome = if step.matched { "matched" } else { "failed" };
+            write!(f, "\n  {:7} {}", outcome, step.rule)?;
+            if let Some(detail) = &step.detail {
+                write!(f, ": {}", detail)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Request of an operator for the explanation of the decision about a
+/// command, signed with its key. Without `time`, the command is evaluated at
+/// the current time.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExplainRequest {
+    pub operator: String,
+    pub name: String,
+    pub command: String,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::wire::address"
+    )]
+    pub source: Option<IpAddr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<DateTime<Utc>>,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default, with = "crate::wire::binary")]
+    pub signature: String,
+}
+
+impl Signed for ExplainRequest {
+    fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    fn signature_mut(&mut self) -> &mut String {
+        &mut self.signature
+    }
+}
+
+impl ExplainRequest {
+    pub fn new(operator: &str, name: &str, command: &str) -> Self {
+        ExplainRequest {
+            operator: operator.to_string(),
+            name: name.to_string(),
+            command: command.to_string(),
+            source: None,
+            time: None,
+            timestamp: chrono::offset::Utc::now(),
+            signature: String::new(),
+        }
+    }
+}
+
+fn step(rule: String, matched: bool, detail: Option<String>) -> Step {
+    Step {
+        rule,
+        matched,
+        detail,
+    }
+}
+
+impl Authorizations {
+    /// Explain the decision about a command of a client, sent from the
+    /// `source` address if known, at the given time.
+    pub fn explain(
+        &self,
+        name: &str,
+        command: &str,
+        source: Option<IpAddr>,
+        time: DateTime<Utc>,
+    ) -> Explanation {
+        let result = self
+            .find_rule(name, source, command, None)
+            .and_then(|(_, rule)| rule.check_schedule(time).map(|_| rule));
+        let (verdict, reason, exec) = match result {
+            Ok(rule) if rule.approval == ApprovalMode::Manual => {
+                (Verdict::ApprovalRequired, None, rule.exec.clone())
+            }
+            Ok(rule) => (Verdict::Allowed, None, rule.exec.clone()),
+            Err(e) => (Verdict::Denied, Some(e.to_string()), ExecContext::default()),
+        };
+        Explanation {
+            name: name.to_string(),
+            command: command.to_string(),
+            source,
+            time,
+            verdict,
+            reason,
+            exec,
+            steps: self.steps(name, command, source, time),
+        }
+    }
+
+    fn steps(
+        &self,
+        name: &str,
+        command: &str,
+        source: Option<IpAddr>,
+        time: DateTime<Utc>,
+    ) -> Vec<Step> {
+        let mut steps = Vec::new();
+        let declared = self
+            .clients()
+            .iter()
+            .filter(|client| client.name == name)
+            .collect::<Vec<_>>();
+        if declared.is_empty() {
+            let rule = format!("client '{}'", name);
+            steps.push(step(rule, false, Some("not declared".to_string())));
+        }
+        let mut clients = Vec::new();
+        for client in declared {
+            let allowed = client.allows_source(source);
+            let sources = match client.sources.is_empty() {
+                true => "any".to_string(),
+                false => client
+                    .sources
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            };
+            let rule = format!("sources of client '{}'", name);
+            steps.push(step(rule, allowed, Some(sources)));
+            if allowed {
+                clients.push(client);
+            }
+        }
+        let mut denied = false;
+        for client in &clients {
+            for pattern in client.deny.iter().filter(|deny| deny.matches(command)) {
+                steps.push(step(format!("deny `{}`", pattern), true, None));
+                denied = true;
+            }
+        }
+        if denied {
+            return steps;
+        }
+        let rules = clients
+            .iter()
+            .flat_map(|client| client.commands.iter())
+            .filter(|rule| rule.action.is_none());
+        for rule in rules {
+            let rule_name = format!("allow `{}`", rule.pattern);
+            if !rule.pattern.matches(command) {
+                steps.push(step(rule_name, false, None));
+                continue;
+            }
+            let detail = match (rule.approval, &rule.approvers) {
+                (ApprovalMode::Auto, _) => None,
+                (ApprovalMode::Manual, None) => {
+                    Some(format!("{} manual approval(s)", rule.approvals.max(1)))
+                }
+                (ApprovalMode::Manual, Some(group)) => Some(format!(
+                    "{} manual approval(s) by group '{}'",
+                    rule.approvals.max(1),
+                    group
+                )),
+            };
+            steps.push(step(rule_name, true, detail));
+            if rule.schedule.is_some() {
+                let rule_name = format!("schedule of `{}`", rule.pattern);
+                let step = match rule.check_schedule(time) {
+                    Ok(_) => step(rule_name, true, None),
+                    Err(e) => step(rule_name, false, Some(e.to_string())),
+                };
+                steps.push(step);
+            }
+            break;
+        }
+        steps
+    }
+}
+
+impl Checker {
+    /// Explain the decision about a command of a client at the current time.
+    pub fn explain(&self, name: &str, command: &str, source: Option<IpAddr>) -> Explanation {
+        self.authorizations()
+            .explain(name, command, source, self.now())
+    }
+
+    /// Explain the decision about a command for an operator, after checking
+    /// the signature of its request, and that it was made recently.
+    pub fn explain_request(&self, request: &ExplainRequest) -> Result<Explanation> {
+        check_timestamp(request.timestamp, self.now())?;
+        let operator = self
+            .authorizations()
+            .operators()
+            .iter()
+            .find(|operator| operator.name == request.operator)
+            .ok_or(Error::Unauthorized)?;
+        if !request.verify(&operator.public_key()?)? {
+            return Err(Error::Unauthorized);
+        }
+        let time = request.time.unwrap_or_else(|| self.now());
+        let explanation =
+            self.authorizations()
+                .explain(&request.name, &request.command, request.source, time);
+        Ok(explanation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authorization::tests::create_checker;
+    use crate::credential::MAX_CLOCK_SKEW;
+    use crate::RsaPrivateKey;
+    use std::path::PathBuf;
+
+    fn outcomes(explanation: &Explanation) -> Vec<(&str, bool)> {
+        explanation
+            .steps
+            .iter()
+            .map(|step| (step.rule.as_str(), step.matched))
+            .collect()
+    }
+
+    #[test]
+    fn explain_decisions() {
+        let checker = create_checker().unwrap();
+        let explanation = checker.explain("Client 1", "date", None);
+        assert_eq!(explanation.verdict, Verdict::Allowed);
+        assert_eq!(explanation.steps.last().unwrap().rule, "allow `date`");
+        let explanation = checker.explain("Client 9", "date", None);
+        assert_eq!(explanation.verdict, Verdict::Denied);
+        assert_eq!(outcomes(&explanation), vec![("client 'Client 9'", false)]);
+        let explanation = checker.explain("Client 1", "/usr/sbin/service sshd stop", None);
+        assert_eq!(explanation.verdict, Verdict::Denied);
+        assert_eq!(
+            outcomes(&explanation),
+            vec![
+                ("sources of client 'Client 1'", true),
+                ("sources of client 'Client 1'", false),
+                ("deny `/usr/sbin/service sshd stop`", true),
+            ]
+        );
+        let explanation = checker.explain("Client 1", "poweroff", None);
+        assert_eq!(explanation.verdict, Verdict::ApprovalRequired);
+        let step = explanation.steps.last().unwrap();
+        assert_eq!(step.detail.as_deref(), Some("1 manual approval(s)"));
+    }
+
+    #[test]
+    fn explain_signed_request() {
+        let checker = create_checker().unwrap();
+        let path: PathBuf = [
+            env!("CARGO_MANIFEST_DIR"),
+            "..",
+            "tests",
+            "operator1.privkey.pem",
+        ]
+        .iter()
+        .collect();
+        let key = RsaPrivateKey::from_uri(&format!("file://{}", path.display())).unwrap();
+        let mut request = ExplainRequest::new("Operator 1", "Client 1", "date");
+        assert!(matches!(
+            checker.explain_request(&request),
+            Err(Error::Unauthorized)
+        ));
+        request.sign(&key).unwrap();
+        let explanation = checker.explain_request(&request).unwrap();
+        assert_eq!(explanation.verdict, Verdict::Allowed);
+        request.operator = "Operator 2".to_string();
+        assert!(checker.explain_request(&request).is_err());
+        let mut request = ExplainRequest::new("Operator 1", "Client 1", "date");
+        request.timestamp -= chrono::Duration::seconds(MAX_CLOCK_SKEW + 1);
+        request.sign(&key).unwrap();
+        assert!(matches!(
+            checker.explain_request(&request),
+            Err(Error::Unauthorized)
+        ));
+    }
+}
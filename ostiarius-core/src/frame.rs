@@ -0,0 +1,163 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Framing of messages over a byte stream, such as a serial line.
+//!
+//! Each frame is made of:
+//!
+//! - the magic bytes `OS`,
+//! - the kind of the frame (1 byte),
+//! - the length of the payload (2 bytes, big endian),
+//! - the payload,
+//! - the CRC-32 (IEEE 802.3) of kind, length and payload (4 bytes, big endian).
+//!
+//! A reader skips any byte preceding the magic bytes, so it can resynchronize
+//! after line noise.
+
+use crate::{Error, Result};
+use std::io::{Read, Write};
+
+const MAGIC: [u8; 2] = *b"OS";
+pub const MAX_PAYLOAD_SIZE: usize = u16::MAX as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Request = 1,
+    Authorization = 2,
+    Denied = 3,
+    Error = 4,
+}
+
+impl TryFrom<u8> for FrameKind {
+    type Error = Error;
+
+    fn try_from(value: u8) -> std::result::Result<Self, Error> {
+        match value {
+            1 => Ok(FrameKind::Request),
+            2 => Ok(FrameKind::Authorization),
+            3 => Ok(FrameKind::Denied),
+            4 => Ok(FrameKind::Error),
+            _ => Err(Error::InvalidFrame(format!("unknown kind {}", value))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub payload: Vec<u8>,
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+impl Frame {
+    pub fn new(kind: FrameKind, payload: Vec<u8>) -> Self {
+        Frame { kind, payload }
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        if self.payload.len() > MAX_PAYLOAD_SIZE {
+            return Err(Error::InvalidFrame("payload too large".to_string()));
+        }
+        let mut data = Vec::with_capacity(self.payload.len() + 9);
+        data.extend_from_slice(&MAGIC);
+        data.push(self.kind as u8);
+        data.extend_from_slice(&(self.payload.len() as u16).to_be_bytes());
+        data.extend_from_slice(&self.payload);
+        let crc = crc32(&data[MAGIC.len()..]);
+        data.extend_from_slice(&crc.to_be_bytes());
+        writer.write_all(&data)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        Self::sync(reader)?;
+        Self::read_body(reader)
+    }
+
+    /// Skip the bytes preceding the magic bytes of the next frame.
+    pub fn sync<R: Read>(reader: &mut R) -> Result<()> {
+        let mut byte = [0u8; 1];
+        let mut matched = 0;
+        while matched < MAGIC.len() {
+            reader.read_exact(&mut byte)?;
+            matched = match byte[0] {
+                b if b == MAGIC[matched] => matched + 1,
+                b if b == MAGIC[0] => 1,
+                _ => 0,
+            };
+        }
+        Ok(())
+    }
+
+    /// Read the rest of a frame, once its magic bytes have been found.
+    pub fn read_body<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut header = [0u8; 3];
+        reader.read_exact(&mut header)?;
+        let size = u16::from_be_bytes([header[1], header[2]]) as usize;
+        let mut payload = vec![0u8; size];
+        reader.read_exact(&mut payload)?;
+        let mut crc = [0u8; 4];
+        reader.read_exact(&mut crc)?;
+        let mut data = header.to_vec();
+        data.extend_from_slice(&payload);
+        if crc32(&data) != u32::from_be_bytes(crc) {
+            return Err(Error::InvalidFrame("checksum mismatch".to_string()));
+        }
+        let kind = FrameKind::try_from(header[0])?;
+        Ok(Frame { kind, payload })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn write_and_read() {
+        let frame = Frame::new(FrameKind::Request, b"{\"name\":\"Client 1\"}".to_vec());
+        let mut data = b"noise O".to_vec();
+        frame.write(&mut data).unwrap();
+        let read = Frame::read(&mut data.as_slice()).unwrap();
+        assert_eq!(read, frame);
+    }
+
+    #[test]
+    fn read_corrupted() {
+        let frame = Frame::new(FrameKind::Authorization, b"payload".to_vec());
+        let mut data = Vec::new();
+        frame.write(&mut data).unwrap();
+        data[6] ^= 0x01;
+        let result = Frame::read(&mut data.as_slice());
+        assert!(matches!(result, Err(Error::InvalidFrame(_))));
+    }
+
+    #[test]
+    fn read_truncated() {
+        let frame = Frame::new(FrameKind::Denied, b"payload".to_vec());
+        let mut data = Vec::new();
+        frame.write(&mut data).unwrap();
+        data.truncate(data.len() - 1);
+        let result = Frame::read(&mut data.as_slice());
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+}
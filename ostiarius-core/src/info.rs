@@ -0,0 +1,33 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::{crypto::fingerprint, Result};
+use openssl::{pkey::Public, rsa::Rsa};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ServerInfo {
+    pub version: String,
+    pub pub_key: String,
+    pub fingerprint: String,
+}
+
+impl ServerInfo {
+    pub fn new(version: &str, pub_key: &Rsa<Public>) -> Result<Self> {
+        let pem = pub_key.public_key_to_pem()?;
+        let info = ServerInfo {
+            version: version.to_string(),
+            pub_key: String::from_utf8_lossy(&pem).into_owned(),
+            fingerprint: fingerprint(pub_key)?,
+        };
+        Ok(info)
+    }
+
+    pub fn pub_key(&self) -> Result<Rsa<Public>> {
+        let key = Rsa::public_key_from_pem(self.pub_key.as_bytes())?;
+        Ok(key)
+    }
+}
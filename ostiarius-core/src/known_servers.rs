@@ -0,0 +1,157 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::{crypto::fingerprint, Error, Result};
+use openssl::{base64, pkey::Public, rsa::Rsa};
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct KnownServer {
+    pub url: String,
+    pub pub_key: Rsa<Public>,
+}
+
+impl KnownServer {
+    pub fn fingerprint(&self) -> Result<String> {
+        fingerprint(&self.pub_key)
+    }
+}
+
+/// List of server public keys trusted by a client, stored one per line as
+/// `<url> <base64 DER public key>`, in the spirit of OpenSSH `known_hosts`.
+#[derive(Debug, Clone, Default)]
+pub struct KnownServers {
+    servers: Vec<KnownServer>,
+}
+
+pub fn normalize_url(url: &str) -> &str {
+    url.trim_end_matches('/')
+}
+
+impl KnownServers {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => contents.parse(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::File::create(path)?;
+        for server in &self.servers {
+            let der = server.pub_key.public_key_to_der()?;
+            writeln!(file, "{} {}", server.url, base64::encode_block(&der))?;
+        }
+        Ok(())
+    }
+
+    pub fn servers(&self) -> &Vec<KnownServer> {
+        &self.servers
+    }
+
+    pub fn find(&self, url: &str) -> Option<&KnownServer> {
+        let url = normalize_url(url);
+        self.servers.iter().find(|server| server.url == url)
+    }
+
+    pub fn insert(&mut self, url: &str, pub_key: Rsa<Public>) {
+        let url = normalize_url(url);
+        self.servers.retain(|server| server.url != url);
+        self.servers.push(KnownServer {
+            url: url.to_string(),
+            pub_key,
+        });
+    }
+}
+
+impl std::str::FromStr for KnownServers {
+    type Err = Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut servers = Vec::new();
+        for (index, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (url, key) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| Error::InvalidKnownServer(index + 1))?;
+            let der = base64::decode_block(key.trim())
+                .map_err(|_| Error::InvalidKnownServer(index + 1))?;
+            let pub_key =
+                Rsa::public_key_from_der(&der).map_err(|_| Error::InvalidKnownServer(index + 1))?;
+            servers.push(KnownServer {
+                url: normalize_url(url).to_string(),
+                pub_key,
+            });
+        }
+        Ok(KnownServers { servers })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn server_pub_key() -> Rsa<Public> {
+        let path: PathBuf = [
+            env!("CARGO_MANIFEST_DIR"),
+            "..",
+            "tests",
+            "server.pubkey.pem",
+        ]
+        .iter()
+        .collect();
+        let pem = std::fs::read(path).unwrap();
+        Rsa::public_key_from_pem(&pem).unwrap()
+    }
+
+    #[test]
+    fn insert_and_find() {
+        let mut known_servers = KnownServers::default();
+        known_servers.insert("http://localhost:3000/", server_pub_key());
+        assert!(known_servers.find("http://localhost:3000").is_some());
+        assert!(known_servers.find("http://localhost:3001").is_none());
+        known_servers.insert("http://localhost:3000", server_pub_key());
+        assert_eq!(known_servers.servers().len(), 1);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("ostiarius-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("known_servers");
+        let mut known_servers = KnownServers::default();
+        known_servers.insert("http://localhost:3000", server_pub_key());
+        known_servers.save(&path).unwrap();
+        let loaded = KnownServers::from_file(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let server = loaded.find("http://localhost:3000").unwrap();
+        assert_eq!(
+            server.fingerprint().unwrap(),
+            fingerprint(&server_pub_key()).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_invalid_line() {
+        let result = "# comment\n\nhttp://localhost:3000 garbage\n".parse::<KnownServers>();
+        assert!(matches!(result, Err(Error::InvalidKnownServer(3))));
+    }
+
+    #[test]
+    fn missing_file_is_empty() {
+        let path = std::env::temp_dir().join(format!("ostiarius-{}", uuid::Uuid::new_v4()));
+        let known_servers = KnownServers::from_file(path).unwrap();
+        assert!(known_servers.servers().is_empty());
+    }
+}
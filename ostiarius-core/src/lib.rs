@@ -4,11 +4,42 @@
 // SPDX-License-Identifier: MIT
 //
 
+pub mod action;
+pub mod approval;
+pub mod armor;
 pub mod authorization;
+pub mod batch;
+pub mod credential;
 pub mod crypto;
 pub mod error;
+pub mod exec;
+pub mod explain;
+pub mod frame;
+pub mod info;
+pub mod known_servers;
+pub mod limits;
+pub mod lint;
+pub mod matcher;
+pub mod offline;
+pub mod policy;
+pub mod problem;
+pub mod roles;
+pub mod schedule;
+#[cfg(unix)]
+pub mod serial;
+pub mod session;
 pub mod utils;
+pub mod wire;
 
+pub use crate::approval::*;
 pub use crate::authorization::*;
-pub use crate::crypto::{password::PasswordProvider, PrivateKey, RsaPrivateKey};
+pub use crate::batch::*;
+pub use crate::credential::Credential;
+pub use crate::crypto::{password::PasswordProvider, PrivateKey, RsaPrivateKey, Signed};
 pub use crate::error::*;
+pub use crate::explain::*;
+pub use crate::info::ServerInfo;
+pub use crate::known_servers::KnownServers;
+pub use crate::offline::*;
+pub use crate::policy::{FileProvider, MemoryProvider, PolicyFormat, PolicyProvider};
+pub use crate::session::*;
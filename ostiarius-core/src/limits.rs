@@ -0,0 +1,370 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Rate limits and daily quotas of clients and commands.
+//!
+//! A rate limit allows a number of `requests` per `interval` of seconds, with
+//! a token bucket: the bucket holds at most `requests` tokens, refilled
+//! continuously, and each request takes one. A daily quota caps the number of
+//! requests per UTC day.
+
+use crate::action::Params;
+use crate::{AuthorizedClient, Checker, Error, Result};
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub interval: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    updated: DateTime<Utc>,
+    /// Time at which the bucket is full again, so its state can be dropped.
+    full: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct Usage {
+    date: NaiveDate,
+    count: u32,
+}
+
+/// Key of the state of a limit: client name, and command if the limit is
+/// specific to it.
+type Key = (String, Option<String>);
+
+/// Interval between two removals of the state of limits which no longer
+/// matters, in seconds.
+const EVICTION_INTERVAL: i64 = 60;
+
+/// State of the rate limits and quotas, kept by the server.
+#[derive(Debug, Default)]
+pub struct Limiter {
+    buckets: HashMap<Key, Bucket>,
+    usages: HashMap<Key, Usage>,
+    evicted: Option<DateTime<Utc>>,
+}
+
+impl Limiter {
+    fn bucket(&self, key: &Key, limit: &RateLimit, now: DateTime<Utc>) -> Bucket {
+        let capacity = f64::from(limit.requests);
+        match self.buckets.get(key) {
+            Some(bucket) => {
+                let elapsed = (now - bucket.updated).num_milliseconds().max(0) as f64 / 1000.0;
+                let refill = elapsed * capacity / limit.interval.max(1) as f64;
+                Bucket {
+                    tokens: (bucket.tokens + refill).min(capacity),
+                    updated: now,
+                    full: bucket.full,
+                }
+            }
+            None => Bucket {
+                tokens: capacity,
+                updated: now,
+                full: now,
+            },
+        }
+    }
+
+    fn usage(&self, key: &Key, now: DateTime<Utc>) -> Usage {
+        let date = now.naive_utc().date();
+        match self.usages.get(key) {
+            Some(usage) if usage.date == date => usage.clone(),
+            _ => Usage { date, count: 0 },
+        }
+    }
+
+    /// Drop the state of full buckets and of past days, as it is the same as
+    /// no state at all.
+    fn evict(&mut self, now: DateTime<Utc>) {
+        if matches!(self.evicted, Some(at) if now - at < Duration::seconds(EVICTION_INTERVAL)) {
+            return;
+        }
+        let date = now.naive_utc().date();
+        self.buckets.retain(|_, bucket| bucket.full > now);
+        self.usages.retain(|_, usage| usage.date == date);
+        self.evicted = Some(now);
+    }
+
+    /// Count a request against several limits at once. A limit given several
+    /// times is counted as many times. The request is only counted if no
+    /// limit is exceeded, otherwise the error tells when to retry.
+    fn acquire(
+        &mut self,
+        rates: &[(Key, RateLimit)],
+        quotas: &[(Key, u32)],
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        self.evict(now);
+        let mut retry_at: Option<DateTime<Utc>> = None;
+        let mut buckets: HashMap<Key, (Bucket, RateLimit)> = HashMap::new();
+        for (key, limit) in rates {
+            let (bucket, _) = buckets
+                .entry(key.clone())
+                .or_insert_with(|| (self.bucket(key, limit, now), *limit));
+            bucket.tokens -= 1.0;
+        }
+        for (bucket, limit) in buckets.values_mut() {
+            let requests = f64::from(limit.requests.max(1));
+            let seconds = |tokens: f64| tokens * limit.interval as f64 / requests;
+            let after =
+                |seconds: f64| now + Duration::milliseconds((seconds * 1000.0).ceil() as i64);
+            if bucket.tokens < 0.0 {
+                retry_at = retry_at.max(Some(after(seconds(-bucket.tokens))));
+            }
+            bucket.full = after(seconds(f64::from(limit.requests) - bucket.tokens));
+        }
+        let mut usages: HashMap<Key, (Usage, u32)> = HashMap::new();
+        for (key, quota) in quotas {
+            let (usage, _) = usages
+                .entry(key.clone())
+                .or_insert_with(|| (self.usage(key, now), *quota));
+            usage.count += 1;
+        }
+        for (usage, quota) in usages.values() {
+            if usage.count > *quota {
+                let at = (usage.date + Duration::days(1)).and_hms_opt(0, 0, 0);
+                retry_at = retry_at.max(at.map(|at| Utc.from_utc_datetime(&at)));
+            }
+        }
+        if retry_at.is_some() {
+            return Err(Error::RateLimited(retry_at));
+        }
+        for (key, (bucket, _)) in buckets {
+            self.buckets.insert(key, bucket);
+        }
+        for (key, (usage, _)) in usages {
+            self.usages.insert(key, usage);
+        }
+        Ok(())
+    }
+}
+
+/// Limits a request counts against, with the keys of their state.
+#[derive(Debug, Default)]
+struct Charges {
+    rates: Vec<(Key, RateLimit)>,
+    quotas: Vec<(Key, u32)>,
+}
+
+impl Checker {
+    /// Get the limits of the client and of a command. The limits of the
+    /// client are given even if no rule allows the command, in which case the
+    /// error of the lookup is returned.
+    fn limits_of(
+        &self,
+        clients: &[&AuthorizedClient],
+        name: &str,
+        source: Option<IpAddr>,
+        command: &str,
+        params: Option<&Params>,
+        charges: &mut Charges,
+    ) -> Result<()> {
+        let result = self.find_rule(name, source, command, params);
+        let client = match &result {
+            Ok((client, _)) => *client,
+            Err(_) => clients[0],
+        };
+        let client_key = (name.to_string(), None);
+        let rates = &mut charges.rates;
+        let quotas = &mut charges.quotas;
+        rates.extend(client.rate_limit.map(|limit| (client_key.clone(), limit)));
+        quotas.extend(client.daily_quota.map(|quota| (client_key, quota)));
+        let (_, rule) = result?;
+        let command_key = (name.to_string(), Some(command.to_string()));
+        rates.extend(rule.rate_limit.map(|limit| (command_key.clone(), limit)));
+        quotas.extend(rule.daily_quota.map(|quota| (command_key, quota)));
+        Ok(())
+    }
+
+    /// Count a request for a command against the rate limits and quotas of
+    /// the client and of the command. Fails with `Error::RateLimited` if any
+    /// of them is exceeded. A command which is not allowed still counts
+    /// against the limits of the client, then its error is returned.
+    ///
+    /// This only needs the policy, so it is meant to be called before the
+    /// challenge of the request is decrypted.
+    pub fn throttle(
+        &self,
+        limiter: &mut Limiter,
+        name: &str,
+        source: Option<IpAddr>,
+        command: &str,
+        params: Option<&Params>,
+    ) -> Result<()> {
+        let clients = self.find_clients(name, source)?;
+        let mut charges = Charges::default();
+        let result = self.limits_of(&clients, name, source, command, params, &mut charges);
+        limiter.acquire(&charges.rates, &charges.quotas, self.now())?;
+        result
+    }
+
+    /// Count the commands of a batch as many requests. Each command counts
+    /// against the limits of the client, and those allowed by a rule against
+    /// the limits of the rule. Nothing is counted if any limit is exceeded.
+    pub fn throttle_batch(
+        &self,
+        limiter: &mut Limiter,
+        name: &str,
+        source: Option<IpAddr>,
+        commands: &[String],
+    ) -> Result<()> {
+        let clients = self.find_clients(name, source)?;
+        let mut charges = Charges::default();
+        for command in commands {
+            // Commands which are not allowed are denied in the batch.
+            let _ = self.limits_of(&clients, name, source, command, None, &mut charges);
+        }
+        limiter.acquire(&charges.rates, &charges.quotas, self.now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(command: Option<&str>) -> Key {
+        ("Client 1".to_string(), command.map(String::from))
+    }
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn rate_limit() {
+        let mut limiter = Limiter::default();
+        let rates = [(
+            key(None),
+            RateLimit {
+                requests: 2,
+                interval: 60,
+            },
+        )];
+        let now = at("2022-12-15T10:00:00Z");
+        assert!(limiter.acquire(&rates, &[], now).is_ok());
+        assert!(limiter.acquire(&rates, &[], now).is_ok());
+        let result = limiter.acquire(&rates, &[], now);
+        assert!(
+            matches!(result, Err(Error::RateLimited(Some(t))) if t == at("2022-12-15T10:00:30Z"))
+        );
+        assert!(limiter
+            .acquire(&rates, &[], at("2022-12-15T10:00:30Z"))
+            .is_ok());
+        assert!(limiter
+            .acquire(&rates, &[], at("2022-12-15T10:00:31Z"))
+            .is_err());
+    }
+
+    #[test]
+    fn throttle_client() {
+        let checker = crate::authorization::tests::create_checker().unwrap();
+        let mut limiter = Limiter::default();
+        let source = "127.0.0.1".parse().ok();
+        for _ in 0..2 {
+            checker
+                .throttle(&mut limiter, "Client 2", source, "uname -a", None)
+                .unwrap();
+        }
+        let result = checker.throttle(&mut limiter, "Client 2", source, "uname -a", None);
+        assert!(matches!(result, Err(Error::RateLimited(Some(_)))));
+        assert!(checker
+            .throttle(&mut limiter, "Client 1", None, "date", None)
+            .is_ok());
+    }
+
+    #[test]
+    fn daily_quota() {
+        let mut limiter = Limiter::default();
+        let rates = [(
+            key(None),
+            RateLimit {
+                requests: 10,
+                interval: 60,
+            },
+        )];
+        let quotas = [(key(Some("backup")), 1)];
+        let now = at("2022-12-15T10:00:00Z");
+        assert!(limiter.acquire(&rates, &quotas, now).is_ok());
+        let result = limiter.acquire(&rates, &quotas, now);
+        assert!(
+            matches!(result, Err(Error::RateLimited(Some(t))) if t == at("2022-12-16T00:00:00Z"))
+        );
+        // The denied request did not take a token from the client bucket.
+        assert_eq!(limiter.bucket(&key(None), &rates[0].1, now).tokens, 9.0);
+        assert!(limiter
+            .acquire(&rates, &quotas, at("2022-12-16T00:00:00Z"))
+            .is_ok());
+    }
+
+    #[test]
+    fn throttle_batch() {
+        let checker = crate::authorization::tests::create_checker().unwrap();
+        let mut limiter = Limiter::default();
+        let source = "127.0.0.1".parse().ok();
+        let commands = vec!["uname -a".to_string(), "reboot".to_string()];
+        assert!(checker
+            .throttle(&mut limiter, "Client 2", source, "uname -a", None)
+            .is_ok());
+        // Nothing is counted when the batch exceeds the limits.
+        let result = checker.throttle_batch(&mut limiter, "Client 2", source, &commands);
+        assert!(matches!(result, Err(Error::RateLimited(Some(_)))));
+        // Limits are shared by all the addresses of a client.
+        let other = "127.0.0.2".parse().ok();
+        assert!(checker
+            .throttle(&mut limiter, "Client 2", other, "uname -a", None)
+            .is_ok());
+        let result = checker.throttle_batch(&mut limiter, "Client 2", other, &commands[..1]);
+        assert!(matches!(result, Err(Error::RateLimited(Some(_)))));
+    }
+
+    #[test]
+    fn throttle_denied() {
+        let checker = crate::authorization::tests::create_checker().unwrap();
+        let mut limiter = Limiter::default();
+        let source = "127.0.0.1".parse().ok();
+        for _ in 0..2 {
+            let result = checker.throttle(&mut limiter, "Client 2", source, "reboot", None);
+            assert!(matches!(result, Err(Error::CommandNotAllowed(_))));
+        }
+        let result = checker.throttle(&mut limiter, "Client 2", source, "reboot", None);
+        assert!(matches!(result, Err(Error::RateLimited(Some(_)))));
+        let result = checker.throttle(&mut limiter, "Client 2", source, "uname -a", None);
+        assert!(matches!(result, Err(Error::RateLimited(Some(_)))));
+    }
+
+    #[test]
+    fn evict() {
+        let mut limiter = Limiter::default();
+        let rates = [(
+            key(None),
+            RateLimit {
+                requests: 2,
+                interval: 60,
+            },
+        )];
+        let quotas = [(key(Some("backup")), 1)];
+        assert!(limiter
+            .acquire(&rates, &quotas, at("2022-12-15T23:59:00Z"))
+            .is_ok());
+        assert!(limiter
+            .acquire(&[], &[], at("2022-12-15T23:59:20Z"))
+            .is_ok());
+        assert_eq!(limiter.buckets.len(), 1);
+        assert_eq!(limiter.usages.len(), 1);
+        assert!(limiter
+            .acquire(&[], &[], at("2022-12-16T00:00:30Z"))
+            .is_ok());
+        assert!(limiter.buckets.is_empty());
+        assert!(limiter.usages.is_empty());
+    }
+}
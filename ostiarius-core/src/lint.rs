@@ -0,0 +1,810 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Validation of an authorizations file, or of a directory of fragments,
+//! with the files and line numbers of the mistakes.
+//!
+//! Besides the errors preventing the file from being loaded, it reports
+//! unknown fields, invalid or short public keys, duplicate names and keys, and
+//! rules which can never apply.
+
+use crate::crypto::fingerprint;
+use crate::policy::PolicyFormat;
+use crate::{Authorizations, Error};
+use openssl::rsa::Rsa;
+use serde::de::{Deserialize, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
+
+/// Minimum size of the public keys, in bits.
+pub const MIN_KEY_BITS: u32 = 2048;
+
+const TOP_FIELDS: &[&str] = &[
+    "clients",
+    "operators",
+    "command_sets",
+    "roles",
+    "actions",
+    "deny",
+];
+const CLIENT_FIELDS: &[&str] = &[
+    "name",
+    "pub_key",
+    "pub_key_file",
+    "sources",
+    "roles",
+    "commands",
+    "deny",
+    "session",
+    "rate_limit",
+    "daily_quota",
+];
+const OPERATOR_FIELDS: &[&str] = &["name", "pub_key", "pub_key_file", "groups"];
+const ROLE_FIELDS: &[&str] = &["inherits", "command_sets", "commands", "deny"];
+const RULE_FIELDS: &[&str] = &[
+    "action",
+    "command",
+    "program",
+    "args",
+    "approval",
+    "approvals",
+    "approvers",
+    "schedule",
+    "rate_limit",
+    "daily_quota",
+    "user",
+    "group",
+    "cwd",
+    "env",
+    "executable",
+    "sha256",
+    "script",
+];
+const PATTERN_FIELDS: &[&str] = &["command", "program", "args"];
+const ACTION_FIELDS: &[&str] = &["program", "args", "params"];
+const ARG_FIELDS: &[&str] = &["glob", "regex", "type", "min", "max", "prefix", "values"];
+const SESSION_FIELDS: &[&str] = &["commands", "duration"];
+const SCHEDULE_FIELDS: &[&str] = &["timezone", "windows", "blackout"];
+const WINDOW_FIELDS: &[&str] = &["days", "from", "to"];
+const RATE_LIMIT_FIELDS: &[&str] = &["requests", "interval"];
+const ENV_FIELDS: &[&str] = &["allow", "strip", "set"];
+const SCRIPT_FIELDS: &[&str] = &["interpreter", "body", "file"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file.display())?;
+        }
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+// Protocol of `toml::Spanned`, used directly to also accept the keys without
+// position, like the one of the maps holding datetimes.
+const SPANNED: &str = "$__toml_private_Spanned";
+const SPANNED_START: &str = "$__toml_private_start";
+const SPANNED_END: &str = "$__toml_private_end";
+const SPANNED_VALUE: &str = "$__toml_private_value";
+const DATETIME_FIELD: &str = "$__toml_private_datetime";
+
+/// Key of a table, with its offset in the file if known.
+struct Key {
+    name: String,
+    offset: Option<usize>,
+}
+
+struct KeyVisitor;
+
+impl<'de> Visitor<'de> for KeyVisitor {
+    type Value = Key;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "a key")
+    }
+
+    fn visit_str<E>(self, name: &str) -> Result<Key, E> {
+        Ok(Key {
+            name: name.to_string(),
+            offset: None,
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Key, A::Error> {
+        let mut key = Key {
+            name: String::new(),
+            offset: None,
+        };
+        while let Some(field) = map.next_key::<String>()? {
+            match field.as_str() {
+                SPANNED_START => key.offset = Some(map.next_value()?),
+                SPANNED_VALUE => key.name = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(key)
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fields = &[SPANNED_START, SPANNED_END, SPANNED_VALUE];
+        deserializer.deserialize_struct(SPANNED, fields, KeyVisitor)
+    }
+}
+
+/// Value of the file, with the positions of its keys and inline values.
+enum Node {
+    Table(Vec<(Key, Spanned<Node>)>),
+    Array(Vec<Spanned<Node>>),
+    String(String),
+    Other,
+}
+
+struct NodeVisitor;
+
+impl<'de> Visitor<'de> for NodeVisitor {
+    type Value = Node;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "a TOML value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<Node, E> {
+        Ok(Node::Other)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<Node, E> {
+        Ok(Node::Other)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<Node, E> {
+        Ok(Node::Other)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<Node, E> {
+        Ok(Node::Other)
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Node, E> {
+        Ok(Node::String(value.to_string()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Node, A::Error> {
+        let mut elements = Vec::new();
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+        Ok(Node::Array(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Node, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(key) = map.next_key::<Key>()? {
+            if key.name == DATETIME_FIELD {
+                map.next_value::<IgnoredAny>()?;
+                return Ok(Node::Other);
+            }
+            entries.push((key, map.next_value()?));
+        }
+        Ok(Node::Table(entries))
+    }
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
+
+impl Node {
+    fn field(&self, name: &str) -> Option<&Spanned<Node>> {
+        match self {
+            Node::Table(entries) => entries
+                .iter()
+                .find(|(key, _)| key.name == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn entries(&self) -> &[(Key, Spanned<Node>)] {
+        match self {
+            Node::Table(entries) => entries,
+            _ => &[],
+        }
+    }
+
+    fn elements(&self) -> &[Spanned<Node>] {
+        match self {
+            Node::Array(elements) => elements,
+            _ => &[],
+        }
+    }
+}
+
+fn field<'a>(node: &'a Spanned<Node>, name: &str) -> Option<&'a Node> {
+    node.get_ref().field(name).map(Spanned::get_ref)
+}
+
+fn elements<'a>(node: &'a Spanned<Node>, name: &str) -> &'a [Spanned<Node>] {
+    field(node, name).map(Node::elements).unwrap_or_default()
+}
+
+/// Offset of a value in the file. The tables declared with a header have no
+/// span, so use the one of their first key.
+fn offset(node: &Spanned<Node>) -> Option<usize> {
+    match (node.span(), node.get_ref()) {
+        ((0, 0), Node::Table(entries)) => entries.first().and_then(|(key, _)| key.offset),
+        ((start, _), _) => Some(start),
+    }
+}
+
+/// Identity owning a public key.
+struct Owner<'a> {
+    kind: &'static str,
+    name: &'a str,
+    offset: Option<usize>,
+}
+
+impl std::fmt::Display for Owner<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} '{}'", self.kind, self.name)
+    }
+}
+
+/// File of the policy, parsed with the positions of its values.
+struct Fragment {
+    file: Option<PathBuf>,
+    contents: String,
+    root: Spanned<Node>,
+}
+
+impl Fragment {
+    /// Directory the key files of the fragment are relative to.
+    fn base(&self) -> &Path {
+        self.file
+            .as_deref()
+            .and_then(Path::parent)
+            .unwrap_or_else(|| Path::new(""))
+    }
+}
+
+struct Linter<'a> {
+    fragment: &'a Fragment,
+    owners: HashMap<String, Owner<'a>>,
+    clients: HashMap<&'a str, String>,
+    operators: HashMap<&'a str, (&'a Fragment, usize)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(fragment: &'a Fragment) -> Self {
+        Linter {
+            fragment,
+            owners: HashMap::new(),
+            clients: HashMap::new(),
+            operators: HashMap::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        let contents = &self.fragment.contents;
+        let offset = offset.min(contents.len());
+        contents.as_bytes()[..offset]
+            .iter()
+            .filter(|&&c| c == b'\n')
+            .count()
+            + 1
+    }
+
+    fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    fn report(&mut self, severity: Severity, offset: Option<usize>, message: String) {
+        let line = offset.map(|offset| self.line(offset));
+        self.diagnostics.push(Diagnostic {
+            severity,
+            file: self.fragment.file.clone(),
+            line,
+            message,
+        });
+    }
+
+    fn check_fields(&mut self, node: &Node, fields: &[&str], what: &str) {
+        for (key, _) in node.entries() {
+            if !fields.contains(&key.name.as_str()) {
+                let message = format!("unknown field `{}` in {}", key.name, what);
+                self.report(Severity::Error, key.offset, message);
+            }
+        }
+    }
+
+    fn check_pattern(&mut self, pattern: &Spanned<Node>, fields: &[&str], what: &str) {
+        // Inline tables have no positions for their keys: report at the
+        // position of the table.
+        let start = self.diagnostics.len();
+        self.check_fields(pattern.get_ref(), fields, what);
+        for arg in elements(pattern, "args") {
+            self.check_fields(arg.get_ref(), ARG_FIELDS, "argument pattern");
+        }
+        if let Some(schedule) = pattern.get_ref().field("schedule") {
+            self.check_fields(schedule.get_ref(), SCHEDULE_FIELDS, "schedule");
+            for window in elements(schedule, "windows") {
+                self.check_fields(window.get_ref(), WINDOW_FIELDS, "time window");
+            }
+        }
+        if let Some(limit) = field(pattern, "rate_limit") {
+            self.check_fields(limit, RATE_LIMIT_FIELDS, "rate limit");
+        }
+        if let Some(env) = field(pattern, "env") {
+            self.check_fields(env, ENV_FIELDS, "environment");
+        }
+        if let Some(script) = field(pattern, "script") {
+            self.check_fields(script, SCRIPT_FIELDS, "script");
+        }
+        let line = offset(pattern).map(|offset| self.line(offset));
+        for diagnostic in &mut self.diagnostics[start..] {
+            diagnostic.line = diagnostic.line.or(line);
+        }
+    }
+
+    fn check_rules(&mut self, rules: &[Spanned<Node>]) {
+        for rule in rules {
+            self.check_pattern(rule, RULE_FIELDS, "command rule");
+        }
+    }
+
+    fn check_denials(&mut self, patterns: &[Spanned<Node>]) {
+        for pattern in patterns {
+            self.check_pattern(pattern, PATTERN_FIELDS, "deny pattern");
+        }
+    }
+
+    fn check_schema(&mut self, root: &Spanned<Node>) {
+        self.check_fields(root.get_ref(), TOP_FIELDS, "authorizations");
+        for client in elements(root, "clients") {
+            self.check_fields(client.get_ref(), CLIENT_FIELDS, "client");
+            self.check_rules(elements(client, "commands"));
+            self.check_denials(elements(client, "deny"));
+            if let Some(session) = field(client, "session") {
+                self.check_fields(session, SESSION_FIELDS, "session");
+            }
+            if let Some(limit) = field(client, "rate_limit") {
+                self.check_fields(limit, RATE_LIMIT_FIELDS, "rate limit");
+            }
+        }
+        for operator in elements(root, "operators") {
+            self.check_fields(operator.get_ref(), OPERATOR_FIELDS, "operator");
+        }
+        for (_, rules) in field(root, "command_sets").map_or(&[][..], Node::entries) {
+            self.check_rules(rules.get_ref().elements());
+        }
+        for (_, role) in field(root, "roles").map_or(&[][..], Node::entries) {
+            self.check_fields(role.get_ref(), ROLE_FIELDS, "role");
+            self.check_rules(elements(role, "commands"));
+            self.check_denials(elements(role, "deny"));
+        }
+        for (_, action) in field(root, "actions").map_or(&[][..], Node::entries) {
+            self.check_fields(action.get_ref(), ACTION_FIELDS, "action");
+            for (_, param) in field(action, "params").map_or(&[][..], Node::entries) {
+                self.check_fields(param.get_ref(), ARG_FIELDS, "parameter pattern");
+            }
+        }
+        self.check_denials(elements(root, "deny"));
+    }
+
+    fn check_keys(&mut self) {
+        let fragment = self.fragment;
+        let root = &fragment.root;
+        let identities = elements(root, "clients")
+            .iter()
+            .map(|node| ("client", node))
+            .chain(
+                elements(root, "operators")
+                    .iter()
+                    .map(|node| ("operator", node)),
+            );
+        for (kind, node) in identities {
+            let name = match field(node, "name") {
+                Some(Node::String(name)) => name,
+                _ => continue,
+            };
+            let key_field = node
+                .get_ref()
+                .field("pub_key")
+                .or_else(|| node.get_ref().field("pub_key_file"));
+            let owner = Owner {
+                kind,
+                name,
+                offset: key_field.and_then(offset).or_else(|| offset(node)),
+            };
+            if kind == "operator" {
+                let position = node.get_ref().field("name").and_then(offset);
+                let line = position.map_or(0, |position| self.line(position));
+                let first = self.operators.insert(name, (fragment, line));
+                if let Some((other, line)) = first {
+                    let message = match &other.file {
+                        Some(file) if !std::ptr::eq(other, fragment) => format!(
+                            "{} already declared in {} at line {}",
+                            owner,
+                            file.display(),
+                            line
+                        ),
+                        _ => format!("{} already declared at line {}", owner, line),
+                    };
+                    self.report(Severity::Error, position, message);
+                }
+            }
+            let pem = match (field(node, "pub_key"), field(node, "pub_key_file")) {
+                (Some(Node::String(pem)), None) => pem.clone(),
+                (None, Some(Node::String(file))) => {
+                    match std::fs::read_to_string(fragment.base().join(file)) {
+                        Ok(pem) => pem,
+                        Err(e) => {
+                            let message = format!("cannot read public key of {}: {}", owner, e);
+                            self.report(Severity::Error, owner.offset, message);
+                            continue;
+                        }
+                    }
+                }
+                (Some(_), Some(_)) => {
+                    let message = format!("both pub_key and pub_key_file given for {}", owner);
+                    self.report(Severity::Error, owner.offset, message);
+                    continue;
+                }
+                (None, None) => {
+                    let message = format!("missing pub_key or pub_key_file for {}", owner);
+                    self.report(Severity::Error, owner.offset, message);
+                    continue;
+                }
+                _ => continue,
+            };
+            let key = match Rsa::public_key_from_pem(pem.as_bytes()) {
+                Ok(key) => key,
+                Err(_) => {
+                    let message = format!("invalid public key of {}", owner);
+                    self.report(Severity::Error, owner.offset, message);
+                    continue;
+                }
+            };
+            let bits = key.size() * 8;
+            if bits < MIN_KEY_BITS {
+                let message = format!(
+                    "public key of {} has {} bits, less than {}",
+                    owner, bits, MIN_KEY_BITS
+                );
+                self.report(Severity::Error, owner.offset, message);
+            }
+            let fingerprint = match fingerprint(&key) {
+                Ok(fingerprint) => fingerprint,
+                Err(_) => continue,
+            };
+            if kind == "client" {
+                // A client can be declared several times, e.g. for different
+                // sources, but always with the same key.
+                if let Some(first) = self.clients.insert(name, fingerprint.clone()) {
+                    if first != fingerprint {
+                        let message = format!("{} is declared with different public keys", owner);
+                        self.report(Severity::Error, owner.offset, message);
+                    }
+                }
+            }
+            match self.owners.get(&fingerprint) {
+                Some(other) if other.kind != owner.kind || other.name != owner.name => {
+                    let message = format!("public key of {} is also used by {}", owner, other);
+                    self.report(Severity::Error, owner.offset, message);
+                }
+                Some(_) => {}
+                None => {
+                    self.owners.insert(fingerprint, owner);
+                }
+            }
+        }
+    }
+
+    fn check_reachability(&mut self, fragments: &'a [Fragment], authorizations: &Authorizations) {
+        let nodes = fragments.iter().flat_map(|fragment| {
+            elements(&fragment.root, "clients")
+                .iter()
+                .map(move |node| (fragment, node))
+        });
+        for (client, (fragment, node)) in authorizations.clients().iter().zip(nodes) {
+            self.fragment = fragment;
+            let own = elements(node, "commands");
+            let roles = node.get_ref().field("roles").and_then(offset);
+            if client.commands.is_empty() && client.session.is_none() {
+                let message = format!("client '{}' is not allowed any command", client.name);
+                self.report(Severity::Warning, offset(node), message);
+            }
+            for (i, rule) in client.commands.iter().enumerate() {
+                let position = own.get(i).and_then(offset).or(roles);
+                let pattern = &rule.pattern;
+                let shadow = client.commands[..i].iter().find(|earlier| {
+                    earlier.action == rule.action && earlier.pattern.covers(pattern)
+                });
+                if let Some(earlier) = shadow {
+                    let message = format!(
+                        "rule `{}` of client '{}' is unreachable, shadowed by `{}`",
+                        pattern, client.name, earlier.pattern
+                    );
+                    self.report(Severity::Warning, position, message);
+                } else if let Some(deny) = client.deny.iter().find(|deny| deny.covers(pattern)) {
+                    let message = format!(
+                        "rule `{}` of client '{}' is always denied by `{}`",
+                        pattern, client.name, deny
+                    );
+                    self.report(Severity::Warning, position, message);
+                }
+            }
+        }
+    }
+}
+
+/// Line of an error of the TOML parser.
+fn error_line(e: &toml::de::Error) -> Option<usize> {
+    e.line_col().map(|(line, _)| line + 1)
+}
+
+/// Describe an error preventing a policy from being loaded.
+fn load_error(e: Error) -> Diagnostic {
+    let (file, e) = match e {
+        Error::InFile(file, e) => (Some(file), *e),
+        e => (None, e),
+    };
+    let (line, message) = match e {
+        Error::Toml(e) => (error_line(&e), e.to_string()),
+        Error::Yaml(e) => (e.location().map(|location| location.line()), e.to_string()),
+        Error::Json(e) => (Some(e.line()).filter(|&line| line > 0), e.to_string()),
+        e => (None, e.to_string()),
+    };
+    Diagnostic {
+        severity: Severity::Error,
+        file,
+        line,
+        message,
+    }
+}
+
+/// Check the files of a policy, then load them with `load` to check its
+/// rules. The diagnostics are sorted by file and line.
+fn check<F>(sources: Vec<(Option<PathBuf>, String)>, load: F) -> Vec<Diagnostic>
+where
+    F: FnOnce() -> crate::Result<Authorizations>,
+{
+    let mut diagnostics = Vec::new();
+    let mut fragments = Vec::new();
+    for (file, contents) in sources {
+        match toml::from_str::<Spanned<Node>>(&contents) {
+            Ok(root) => fragments.push(Fragment {
+                file,
+                contents,
+                root,
+            }),
+            Err(e) => diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                file,
+                line: error_line(&e),
+                message: e.to_string(),
+            }),
+        }
+    }
+    if let Some(first) = fragments.first() {
+        let mut linter = Linter::new(first);
+        for fragment in &fragments {
+            linter.fragment = fragment;
+            linter.check_schema(&fragment.root);
+            linter.check_keys();
+        }
+        if !linter.has_errors() && diagnostics.is_empty() {
+            match load() {
+                Ok(authorizations) => linter.check_reachability(&fragments, &authorizations),
+                Err(e) => diagnostics.push(load_error(e)),
+            }
+        }
+        diagnostics.extend(linter.diagnostics);
+    }
+    diagnostics.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    diagnostics
+}
+
+/// Check the contents of an authorizations file. The diagnostics are sorted
+/// by line.
+pub fn lint(contents: &str) -> Vec<Diagnostic> {
+    check(vec![(None, contents.to_string())], || {
+        let authorizations = toml::from_str(contents)?;
+        Ok(authorizations)
+    })
+}
+
+/// Check an authorizations file, or all the fragments of a directory. The
+/// diagnostics are sorted by file and line. YAML and JSON files are only
+/// checked for errors preventing them from being loaded.
+pub fn lint_path<P: AsRef<Path>>(path: P, format: PolicyFormat) -> crate::Result<Vec<Diagnostic>> {
+    let path = path.as_ref();
+    if format != PolicyFormat::Toml {
+        let result = Authorizations::read(path, format);
+        return Ok(result.err().map(load_error).into_iter().collect());
+    }
+    let mut sources = Vec::new();
+    for file in format.files(path)? {
+        let contents = std::fs::read_to_string(&file)?;
+        sources.push((Some(file), contents));
+    }
+    Ok(check(sources, || Authorizations::from_file(path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lint a file, with `PUB_KEY` replaced by a public key on a single line.
+    fn messages(contents: &str) -> Vec<String> {
+        let pub_key = include_str!("../../tests/server.pubkey.pem").replace('\n', "\\n");
+        let contents = contents.replace("PUB_KEY", &pub_key);
+        lint(&contents).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn lint_valid_file() {
+        let contents = include_str!("../../tests/authorizations.toml");
+        assert!(lint(contents).is_empty());
+    }
+
+    #[test]
+    fn lint_syntax_error() {
+        let messages = messages("[[clients]]\nname = \"Client 1\nx");
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("line 2: error:"));
+    }
+
+    #[test]
+    fn lint_mistakes() {
+        let short = Rsa::generate(1024).unwrap().public_key_to_pem().unwrap();
+        let short = String::from_utf8(short).unwrap().replace('\n', "\\n");
+        let contents = r#"
+[[clients]]
+name = "Client 1"
+pub_key = "PUB_KEY"
+comands = ["date"]
+
+[[clients]]
+name = "Client 2"
+pub_key = "PUB_KEY"
+commands = [
+    { command = "date", aproval = "manual", schedule = { blackout = [2022-12-25], window = [] } },
+]
+
+[[operators]]
+name = "Operator 1"
+pub_key = "SHORT_KEY"
+
+[[operators]]
+name = "Operator 1"
+pub_key = "garbage"
+"#;
+        assert_eq!(
+            messages(&contents.replace("SHORT_KEY", &short)),
+            vec![
+                "line 5: error: unknown field `comands` in client",
+                "line 9: error: public key of client 'Client 2' is also used by client 'Client 1'",
+                "line 11: error: unknown field `aproval` in command rule",
+                "line 11: error: unknown field `window` in schedule",
+                "line 16: error: public key of operator 'Operator 1' has 1024 bits, less than 2048",
+                "line 19: error: operator 'Operator 1' already declared at line 15",
+                "line 20: error: invalid public key of operator 'Operator 1'",
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_unreachable_rules() {
+        let messages = messages(
+            r#"
+deny = ["reboot"]
+
+[[clients]]
+name = "Client 1"
+pub_key = "PUB_KEY"
+commands = [
+    { program = "journalctl", args = ["-n", { type = "integer", max = 100 }] },
+    "journalctl -n 10",
+    "reboot",
+]
+
+[[clients]]
+name = "Client 1"
+pub_key = "PUB_KEY"
+"#,
+        );
+        assert_eq!(
+            messages,
+            vec![
+                "line 9: warning: rule `journalctl -n 10` of client 'Client 1' is unreachable, shadowed by `journalctl -n <integer>`",
+                "line 10: warning: rule `reboot` of client 'Client 1' is always denied by `reboot`",
+                "line 14: warning: client 'Client 1' is not allowed any command",
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_fragments() {
+        let dir = std::env::temp_dir().join(format!("ostiarius-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pub_key = include_str!("../../tests/server.pubkey.pem");
+        std::fs::write(dir.join("operator.pem"), pub_key).unwrap();
+        let write = |name: &str, contents: &str| std::fs::write(dir.join(name), contents).unwrap();
+        write(
+            "10-operators.toml",
+            "[[operators]]\nname = \"Operator 1\"\npub_key_file = \"operator.pem\"\n",
+        );
+        write(
+            "20-clients.toml",
+            r#"
+[[clients]]
+name = "Client 1"
+pub_key_file = "missing.pem"
+commands = ["date"]
+
+[[operators]]
+name = "Operator 1"
+pub_key_file = "operator.pem"
+"#,
+        );
+        let diagnostics = lint_path(&dir, PolicyFormat::Toml).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let messages = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let file = diagnostic.file.as_ref().unwrap().file_name().unwrap();
+                let line = diagnostic.line.unwrap();
+                format!(
+                    "{}:{}: {}",
+                    file.to_string_lossy(),
+                    line,
+                    diagnostic.message
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("20-clients.toml:4: cannot read public key of client"));
+        assert!(messages[1]
+            .starts_with("20-clients.toml:8: operator 'Operator 1' already declared in "));
+        assert!(messages[1].ends_with("10-operators.toml at line 2"));
+    }
+}
@@ -0,0 +1,362 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Matching of commands against the patterns of the authorizations.
+//!
+//! A command is split into arguments on whitespace, the same way the client
+//! executes it. A pattern gives the program, which must match exactly, and a
+//! pattern for each argument:
+//!
+//! - a string, matching the argument literally,
+//! - `{ glob = "..." }`, where `*` matches any sequence of characters and `?`
+//!   any single character,
+//! - `{ regex = "..." }`, which must match the whole argument,
+//! - `{ type = "integer", min = ..., max = ... }`, with optional bounds,
+//! - `{ type = "path", prefix = "..." }`, an absolute path under a prefix,
+//!   without any `..` component,
+//! - `{ type = "enum", values = [...] }`, one of the given values.
+
+use crate::utils::glob_match;
+use regex::Regex;
+use serde::Deserialize;
+use std::path::{Component, Path};
+
+/// Split a command into arguments.
+pub fn argv(command: &str) -> Vec<&str> {
+    command.split_ascii_whitespace().collect()
+}
+
+/// Regular expression matching a whole argument.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl TryFrom<String> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Regex::new(&format!("^(?:{})$", value)).map(Pattern)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TypedArg {
+    Integer { min: Option<i64>, max: Option<i64> },
+    Path { prefix: String },
+    Enum { values: Vec<String> },
+}
+
+impl TypedArg {
+    pub fn matches(&self, arg: &str) -> bool {
+        match self {
+            TypedArg::Integer { min, max } => match arg.parse::<i64>() {
+                Ok(value) => value >= min.unwrap_or(i64::MIN) && value <= max.unwrap_or(i64::MAX),
+                Err(_) => false,
+            },
+            TypedArg::Path { prefix } => {
+                let path = Path::new(arg);
+                path.is_absolute()
+                    && !path.components().any(|c| c == Component::ParentDir)
+                    && path.starts_with(prefix)
+            }
+            TypedArg::Enum { values } => values.iter().any(|value| value == arg),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ArgPattern {
+    Literal(String),
+    Glob {
+        glob: String,
+    },
+    Regex {
+        #[serde(deserialize_with = "deserialize_pattern")]
+        regex: Pattern,
+    },
+    Typed(TypedArg),
+}
+
+fn deserialize_pattern<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Pattern, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    Pattern::try_from(value).map_err(serde::de::Error::custom)
+}
+
+impl ArgPattern {
+    pub fn matches(&self, arg: &str) -> bool {
+        match self {
+            ArgPattern::Literal(literal) => literal == arg,
+            ArgPattern::Glob { glob } => glob_match(glob, arg),
+            ArgPattern::Regex { regex } => regex.0.is_match(arg),
+            ArgPattern::Typed(typed) => typed.matches(arg),
+        }
+    }
+
+    /// Whether every argument matched by `other` is also matched by this
+    /// pattern. May give false negatives, e.g. between two regexes.
+    pub fn covers(&self, other: &ArgPattern) -> bool {
+        match (self, other) {
+            (ArgPattern::Glob { glob }, _) if glob == "*" => true,
+            (_, ArgPattern::Literal(literal)) => self.matches(literal),
+            (_, ArgPattern::Typed(TypedArg::Enum { values })) => {
+                values.iter().all(|value| self.matches(value))
+            }
+            (ArgPattern::Glob { glob }, ArgPattern::Glob { glob: other }) => glob == other,
+            (ArgPattern::Regex { regex }, ArgPattern::Regex { regex: other }) => {
+                regex.0.as_str() == other.0.as_str()
+            }
+            (
+                ArgPattern::Typed(TypedArg::Integer { min, max }),
+                ArgPattern::Typed(TypedArg::Integer {
+                    min: other_min,
+                    max: other_max,
+                }),
+            ) => {
+                min.unwrap_or(i64::MIN) <= other_min.unwrap_or(i64::MIN)
+                    && max.unwrap_or(i64::MAX) >= other_max.unwrap_or(i64::MAX)
+            }
+            (
+                ArgPattern::Typed(TypedArg::Path { prefix }),
+                ArgPattern::Typed(TypedArg::Path { prefix: other }),
+            ) => Path::new(other).starts_with(prefix),
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for ArgPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgPattern::Literal(literal) => write!(f, "{}", literal),
+            ArgPattern::Glob { glob } => write!(f, "<glob:{}>", glob),
+            ArgPattern::Regex { regex } => write!(f, "<regex:{}>", regex.0.as_str()),
+            ArgPattern::Typed(TypedArg::Integer { .. }) => write!(f, "<integer>"),
+            ArgPattern::Typed(TypedArg::Path { prefix }) => write!(f, "<path:{}>", prefix),
+            ArgPattern::Typed(TypedArg::Enum { values }) => write!(f, "<{}>", values.join("|")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum PatternEntry {
+    Command(String),
+    Table {
+        command: Option<String>,
+        program: Option<String>,
+        #[serde(default)]
+        args: Vec<ArgPattern>,
+    },
+}
+
+/// Pattern of a command: program and arguments. It is given either as a
+/// plain string, or as a table with a `command`, or a `program` with patterns
+/// for its `args`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "PatternEntry")]
+pub struct CommandPattern {
+    pub program: String,
+    pub args: Vec<ArgPattern>,
+}
+
+impl TryFrom<PatternEntry> for CommandPattern {
+    type Error = String;
+
+    fn try_from(entry: PatternEntry) -> Result<Self, Self::Error> {
+        match entry {
+            PatternEntry::Command(command) => Ok(CommandPattern::literal(&command)),
+            PatternEntry::Table {
+                command,
+                program,
+                args,
+            } => CommandPattern::from_parts(command, program, args),
+        }
+    }
+}
+
+impl CommandPattern {
+    pub(crate) fn from_parts(
+        command: Option<String>,
+        program: Option<String>,
+        args: Vec<ArgPattern>,
+    ) -> Result<Self, String> {
+        match (command, program) {
+            (Some(command), None) if args.is_empty() => Ok(CommandPattern::literal(&command)),
+            (None, Some(program)) => Ok(CommandPattern { program, args }),
+            _ => Err("expected either `command` or `program` and `args`".into()),
+        }
+    }
+
+    /// Pattern matching exactly the arguments of a command.
+    pub fn literal(command: &str) -> Self {
+        let argv = argv(command);
+        CommandPattern {
+            program: argv.first().copied().unwrap_or_default().to_string(),
+            args: argv[1.min(argv.len())..]
+                .iter()
+                .map(|arg| ArgPattern::Literal(arg.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Whether every command matched by `other` is also matched by this
+    /// pattern.
+    pub fn covers(&self, other: &CommandPattern) -> bool {
+        self.program == other.program
+            && self.args.len() == other.args.len()
+            && self
+                .args
+                .iter()
+                .zip(other.args.iter())
+                .all(|(pattern, other)| pattern.covers(other))
+    }
+
+    /// Whether some command matched by a glob, as given in a session policy,
+    /// may also be matched by this pattern. May give false positives.
+    pub fn may_match_glob(&self, glob: &str) -> bool {
+        let wildcard = match glob.find(['*', '?']) {
+            Some(wildcard) => wildcard,
+            None => return self.matches(glob),
+        };
+        if self
+            .args
+            .iter()
+            .all(|arg| matches!(arg, ArgPattern::Literal(_)))
+        {
+            return glob_match(glob, &self.to_string());
+        }
+        let prefix = glob[..wildcard].trim_start();
+        match prefix.split_once(|c: char| c.is_ascii_whitespace()) {
+            Some((program, _)) => program == self.program,
+            None => self.program.starts_with(prefix),
+        }
+    }
+
+    pub fn matches(&self, command: &str) -> bool {
+        match argv(command).split_first() {
+            Some((program, args)) => {
+                *program == self.program
+                    && args.len() == self.args.len()
+                    && self
+                        .args
+                        .iter()
+                        .zip(args.iter())
+                        .all(|(pattern, arg)| pattern.matches(arg))
+            }
+            None => false,
+        }
+    }
+}
+
+impl std::fmt::Display for CommandPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Args {
+        args: Vec<ArgPattern>,
+    }
+
+    fn parse(program: &str, args: &str) -> CommandPattern {
+        let args: Args = toml::from_str(&format!("args = {}", args)).unwrap();
+        CommandPattern {
+            program: program.to_string(),
+            args: args.args,
+        }
+    }
+
+    #[test]
+    fn match_literal() {
+        let pattern = CommandPattern::literal("systemctl restart nginx");
+        assert!(pattern.matches("systemctl restart nginx"));
+        assert!(pattern.matches("systemctl  restart\tnginx "));
+        assert!(!pattern.matches("systemctl restart nginx now"));
+        assert!(!pattern.matches("systemctl restart"));
+        assert!(!pattern.matches(""));
+        assert_eq!(pattern.to_string(), "systemctl restart nginx");
+    }
+
+    #[test]
+    fn match_glob_and_regex() {
+        let pattern = parse(
+            "/usr/bin/systemctl",
+            r#"[{ glob = "re*" }, { regex = "nginx(@[a-z]+)?" }]"#,
+        );
+        assert!(pattern.matches("/usr/bin/systemctl restart nginx"));
+        assert!(pattern.matches("/usr/bin/systemctl reload nginx@main"));
+        assert!(!pattern.matches("/usr/bin/systemctl stop nginx"));
+        assert!(!pattern.matches("/usr/bin/systemctl restart nginx2"));
+        assert!(!pattern.matches("systemctl restart nginx"));
+    }
+
+    #[test]
+    fn match_typed() {
+        let pattern = parse(
+            "/bin/journalctl",
+            r#"[{ type = "enum", values = ["-n", "--lines"] }, { type = "integer", min = 1, max = 1000 }]"#,
+        );
+        assert!(pattern.matches("/bin/journalctl -n 100"));
+        assert!(pattern.matches("/bin/journalctl --lines 1000"));
+        assert!(!pattern.matches("/bin/journalctl -n 0"));
+        assert!(!pattern.matches("/bin/journalctl -n ten"));
+        assert!(!pattern.matches("/bin/journalctl -f 10"));
+        let path = parse("/bin/cat", r#"[{ type = "path", prefix = "/var/log" }]"#);
+        assert!(path.matches("/bin/cat /var/log/syslog"));
+        assert!(!path.matches("/bin/cat /var/log/../../etc/shadow"));
+        assert!(!path.matches("/bin/cat /var/logs/syslog"));
+        assert!(!path.matches("/bin/cat var/log/syslog"));
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let result = toml::from_str::<Args>(r#"args = [{ regex = "(" }]"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn match_session_glob() {
+        let pattern = CommandPattern::literal("/usr/sbin/service sshd stop");
+        assert!(pattern.may_match_glob("/usr/sbin/service *"));
+        assert!(pattern.may_match_glob("/usr/sbin/service sshd stop"));
+        assert!(!pattern.may_match_glob("/usr/sbin/service nginx *"));
+        assert!(!pattern.may_match_glob("date *"));
+        let pattern = parse("/usr/bin/tail", r#"["-n", { type = "integer" }]"#);
+        assert!(pattern.may_match_glob("/usr/bin/tail -n 10"));
+        assert!(!pattern.may_match_glob("/usr/bin/tail -f 10"));
+        assert!(pattern.may_match_glob("/usr/bin/t*"));
+        assert!(pattern.may_match_glob("*"));
+        assert!(!pattern.may_match_glob("/usr/bin/head *"));
+    }
+
+    #[test]
+    fn pattern_covers() {
+        let wide = parse(
+            "/bin/journalctl",
+            r#"[{ glob = "-*" }, { type = "integer", min = 1 }]"#,
+        );
+        let narrow = parse(
+            "/bin/journalctl",
+            r#"[{ type = "enum", values = ["-n", "--lines"] }, { type = "integer", min = 1, max = 1000 }]"#,
+        );
+        assert!(wide.covers(&narrow));
+        assert!(!narrow.covers(&wide));
+        assert!(wide.covers(&CommandPattern::literal("/bin/journalctl -n 10")));
+        assert!(!wide.covers(&CommandPattern::literal("/bin/journalctl -n")));
+    }
+}
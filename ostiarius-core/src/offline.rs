@@ -0,0 +1,79 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::{armor, Authorization, Request, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const REQUEST_LABEL: &str = "REQUEST";
+const AUTHORIZATION_LABEL: &str = "AUTHORIZATION";
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OfflineRequest {
+    pub id: Uuid,
+    pub request: Request,
+}
+
+impl OfflineRequest {
+    pub fn new(request: Request) -> Self {
+        OfflineRequest {
+            id: Uuid::new_v4(),
+            request,
+        }
+    }
+
+    pub fn to_armor(&self) -> Result<String> {
+        Ok(armor::encode(REQUEST_LABEL, &serde_json::to_vec(self)?))
+    }
+
+    pub fn from_armor(text: &str) -> Result<Self> {
+        let data = armor::decode(REQUEST_LABEL, text)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OfflineAuthorization {
+    pub request_id: Uuid,
+    pub authorization: Authorization,
+}
+
+impl OfflineAuthorization {
+    pub fn to_armor(&self) -> Result<String> {
+        Ok(armor::encode(
+            AUTHORIZATION_LABEL,
+            &serde_json::to_vec(self)?,
+        ))
+    }
+
+    pub fn from_armor(text: &str) -> Result<Self> {
+        let data = armor::decode(AUTHORIZATION_LABEL, text)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authorization::tests::{create_checker, create_requester};
+
+    #[test]
+    fn offline_exchange() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = OfflineRequest::new(requester.make("Client 1", "date").unwrap());
+        let armored = request.to_armor().unwrap();
+        let request = OfflineRequest::from_armor(&armored).unwrap();
+        let authorization = OfflineAuthorization {
+            request_id: request.id,
+            authorization: checker.check(&request.request, None).unwrap(),
+        };
+        let armored = authorization.to_armor().unwrap();
+        let authorization = OfflineAuthorization::from_armor(&armored).unwrap();
+        assert_eq!(authorization.request_id, request.id);
+        assert!(requester.check(&authorization.authorization).unwrap());
+    }
+}
@@ -0,0 +1,244 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Sources of the authorizations enforced by a checker.
+//!
+//! The authorizations are loaded by a `PolicyProvider`, when the checker is
+//! created and whenever it is reloaded. The crate provides providers reading
+//! TOML, YAML or JSON files, and one keeping the authorizations in memory.
+//! Other sources, like a database, can be plugged in by implementing the
+//! trait: `Authorizations` can be deserialized from any `serde` format.
+
+use crate::{Authorizations, Error, Result};
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// Source of authorizations.
+pub trait PolicyProvider: std::fmt::Debug + Send + Sync {
+    /// Load the current authorizations.
+    fn load(&self) -> Result<Authorizations>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PolicyFormat {
+    #[default]
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl PolicyFormat {
+    /// Guess the format of a file from its extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        PolicyFormat::from_extension(extension)
+    }
+
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "toml" => Some(PolicyFormat::Toml),
+            "yaml" | "yml" => Some(PolicyFormat::Yaml),
+            "json" => Some(PolicyFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned>(&self, contents: &str) -> Result<T> {
+        let value = match self {
+            PolicyFormat::Toml => toml::from_str(contents)?,
+            PolicyFormat::Yaml => serde_yaml::from_str(contents)?,
+            PolicyFormat::Json => serde_json::from_str(contents)?,
+        };
+        Ok(value)
+    }
+
+    /// Files holding a policy: the given file, or the fragments of the given
+    /// directory in this format, sorted by name. Hidden files are ignored.
+    pub fn files<P: AsRef<Path>>(&self, path: P) -> Result<Vec<PathBuf>> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Ok(vec![path.to_path_buf()]);
+        }
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let file = entry?.path();
+            let hidden = matches!(
+                file.file_name(),
+                Some(name) if name.to_string_lossy().starts_with('.')
+            );
+            if !hidden && PolicyFormat::from_path(&file) == Some(*self) && file.is_file() {
+                files.push(file);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl FromStr for PolicyFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        PolicyFormat::from_extension(s)
+            .ok_or_else(|| Error::InvalidPolicy(format!("unknown format '{}'", s)))
+    }
+}
+
+/// Authorizations read from a file, or from the fragments of a directory.
+#[derive(Debug, Clone)]
+pub struct FileProvider {
+    path: PathBuf,
+    format: PolicyFormat,
+}
+
+impl FileProvider {
+    pub fn new<P: AsRef<Path>>(path: P, format: PolicyFormat) -> Self {
+        FileProvider {
+            path: path.as_ref().to_path_buf(),
+            format,
+        }
+    }
+
+    pub fn toml<P: AsRef<Path>>(path: P) -> Self {
+        FileProvider::new(path, PolicyFormat::Toml)
+    }
+
+    pub fn yaml<P: AsRef<Path>>(path: P) -> Self {
+        FileProvider::new(path, PolicyFormat::Yaml)
+    }
+
+    pub fn json<P: AsRef<Path>>(path: P) -> Self {
+        FileProvider::new(path, PolicyFormat::Json)
+    }
+
+    /// Read a file in the format given by its extension, TOML by default.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        let format = PolicyFormat::from_path(&path).unwrap_or_default();
+        FileProvider::new(path, format)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn format(&self) -> PolicyFormat {
+        self.format
+    }
+}
+
+impl PolicyProvider for FileProvider {
+    fn load(&self) -> Result<Authorizations> {
+        Authorizations::read(&self.path, self.format)
+    }
+}
+
+/// Authorizations kept in memory, which can be replaced at any time, e.g. in
+/// tests.
+#[derive(Debug, Clone)]
+pub struct MemoryProvider {
+    authorizations: Arc<RwLock<Authorizations>>,
+}
+
+impl MemoryProvider {
+    pub fn new(authorizations: Authorizations) -> Self {
+        MemoryProvider {
+            authorizations: Arc::new(RwLock::new(authorizations)),
+        }
+    }
+
+    /// Parse the authorizations from a string in the given format.
+    pub fn parse(contents: &str, format: PolicyFormat) -> Result<Self> {
+        Ok(MemoryProvider::new(format.parse(contents)?))
+    }
+
+    /// Replace the authorizations returned by the next loads.
+    pub fn replace(&self, authorizations: Authorizations) {
+        match self.authorizations.write() {
+            Ok(mut current) => *current = authorizations,
+            Err(poisoned) => *poisoned.into_inner() = authorizations,
+        }
+    }
+}
+
+impl PolicyProvider for MemoryProvider {
+    fn load(&self) -> Result<Authorizations> {
+        let authorizations = match self.authorizations.read() {
+            Ok(authorizations) => authorizations.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        Ok(authorizations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authorization::tests::create_checker;
+    use crate::Checker;
+
+    fn data_path(name: &str) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "..", "tests", name]
+            .iter()
+            .collect()
+    }
+
+    #[test]
+    fn load_formats() {
+        let pub_key = include_str!("../../tests/server.pubkey.pem").replace('\n', "\\n");
+        let yaml = format!(
+            r#"
+clients:
+  - name: Client 1
+    pub_key: "{}"
+    commands:
+      - date
+      - command: poweroff
+        approval: manual
+      - command: apt-get upgrade
+        schedule:
+          blackout: [2022-12-25]
+"#,
+            pub_key
+        );
+        let json = format!(
+            r#"{{ "clients": [{{ "name": "Client 1", "pub_key": "{}", "commands": ["date"] }}] }}"#,
+            pub_key
+        );
+        for (contents, format) in [(yaml, PolicyFormat::Yaml), (json, PolicyFormat::Json)] {
+            let provider = MemoryProvider::parse(&contents, format).unwrap();
+            let authorizations = provider.load().unwrap();
+            assert!(authorizations.clients()[0].is_allowed("date"));
+        }
+        assert!(MemoryProvider::parse("clients: 1", PolicyFormat::Yaml).is_err());
+        let provider = FileProvider::from_path(data_path("authorizations.toml"));
+        assert_eq!(provider.format(), PolicyFormat::Toml);
+        assert!(provider.load().is_ok());
+        assert_eq!(
+            PolicyFormat::from_path("policy.yml"),
+            Some(PolicyFormat::Yaml)
+        );
+        assert!("xml".parse::<PolicyFormat>().is_err());
+    }
+
+    #[test]
+    fn reload_from_provider() {
+        let authorizations = create_checker().unwrap().authorizations().clone();
+        let provider = Arc::new(MemoryProvider::new(authorizations));
+        let uri = format!(
+            "file://{}?password=1234",
+            data_path("server.privkey.pem").display()
+        );
+        let checker = Checker::with_provider(&uri, provider.clone()).unwrap();
+        let allowed = |checker: &Checker| checker.authorizations().clients()[0].is_allowed("date");
+        assert!(allowed(&checker));
+        provider.replace(Authorizations::default());
+        assert!(allowed(&checker));
+        let checker = checker.reload().unwrap();
+        assert!(checker.authorizations().clients().is_empty());
+    }
+}
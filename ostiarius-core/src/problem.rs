@@ -0,0 +1,158 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Description of the rejection of a request, as problem details ([RFC 7807]).
+//!
+//! [RFC 7807]: https://www.rfc-editor.org/rfc/rfc7807
+
+use crate::Error;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Machine-readable reason of a rejection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Reason {
+    Forbidden,
+    UnknownClient,
+    CommandNotAllowed,
+    InvalidParameter,
+    OutsideTimeWindow,
+    BadChallenge,
+    RateLimited,
+    ApprovalDenied,
+    InternalError,
+}
+
+impl Reason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Reason::Forbidden => "forbidden",
+            Reason::UnknownClient => "unknown-client",
+            Reason::CommandNotAllowed => "command-not-allowed",
+            Reason::InvalidParameter => "invalid-parameter",
+            Reason::OutsideTimeWindow => "outside-time-window",
+            Reason::BadChallenge => "bad-challenge",
+            Reason::RateLimited => "rate-limited",
+            Reason::ApprovalDenied => "approval-denied",
+            Reason::InternalError => "internal-error",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Reason::Forbidden => "Forbidden",
+            Reason::UnknownClient => "Unknown client",
+            Reason::CommandNotAllowed => "Command not allowed",
+            Reason::InvalidParameter => "Invalid parameter",
+            Reason::OutsideTimeWindow => "Outside time window",
+            Reason::BadChallenge => "Bad challenge",
+            Reason::RateLimited => "Rate limited",
+            Reason::ApprovalDenied => "Approval denied",
+            Reason::InternalError => "Internal error",
+        }
+    }
+
+    /// HTTP status code of the rejection.
+    pub fn status(&self) -> u16 {
+        match self {
+            Reason::InvalidParameter | Reason::BadChallenge => 400,
+            Reason::RateLimited => 429,
+            Reason::InternalError => 500,
+            _ => 403,
+        }
+    }
+}
+
+impl From<&Error> for Reason {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::Unauthorized | Error::SourceNotAllowed(_) | Error::SessionNotAllowed(_) => {
+                Reason::Forbidden
+            }
+            Error::UnknownClient(_) => Reason::UnknownClient,
+            Error::CommandNotAllowed(_) | Error::CommandDenied(_) => Reason::CommandNotAllowed,
+            Error::InvalidParameter(_) => Reason::InvalidParameter,
+            Error::OutsideTimeWindow(_) => Reason::OutsideTimeWindow,
+            Error::BadChallenge => Reason::BadChallenge,
+            Error::RateLimited(_) => Reason::RateLimited,
+            _ => Reason::InternalError,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub reason: Reason,
+    /// Earliest time at which the request may succeed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_at: Option<DateTime<Utc>>,
+}
+
+impl Problem {
+    pub fn new(reason: Reason) -> Self {
+        Problem {
+            kind: format!("urn:ostiarius:problem:{}", reason.name()),
+            title: reason.title().to_string(),
+            status: reason.status(),
+            detail: None,
+            reason,
+            retry_at: None,
+        }
+    }
+
+    pub fn with_detail(self, detail: &str) -> Self {
+        Problem {
+            detail: Some(detail.to_string()),
+            ..self
+        }
+    }
+}
+
+/// Describe an error, without details for internal errors.
+impl From<&Error> for Problem {
+    fn from(error: &Error) -> Self {
+        let mut problem = Problem::new(Reason::from(error));
+        if let Error::OutsideTimeWindow(retry_at) | Error::RateLimited(retry_at) = error {
+            problem.retry_at = *retry_at;
+        }
+        match problem.reason {
+            Reason::InternalError => problem,
+            _ => problem.with_detail(&error.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn problem_from_error() {
+        let problem = Problem::from(&Error::CommandNotAllowed("reboot".to_string()));
+        assert_eq!(problem.reason, Reason::CommandNotAllowed);
+        assert_eq!(problem.status, 403);
+        assert_eq!(
+            problem.detail.as_deref(),
+            Some("Command not allowed: reboot")
+        );
+        let json = serde_json::to_value(&problem).unwrap();
+        assert_eq!(json["type"], "urn:ostiarius:problem:command-not-allowed");
+        assert_eq!(json["reason"], "command-not-allowed");
+        let problem = Problem::from(&Error::InvalidKey("secret".to_string()));
+        assert_eq!(problem.reason, Reason::InternalError);
+        assert_eq!(problem.status, 500);
+        assert!(problem.detail.is_none());
+    }
+}
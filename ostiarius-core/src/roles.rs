@@ -0,0 +1,187 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Named command sets and roles shared by several clients.
+//!
+//! A role grants the commands of its `command_sets`, its own `commands` and
+//! those of the roles it `inherits` from, and likewise denies its own `deny`
+//! patterns and those of the roles it inherits from. The roles of a client are
+//! expanded into its lists of commands and denied patterns when the
+//! authorizations are loaded.
+
+use crate::matcher::CommandPattern;
+use crate::{AuthorizedClient, CommandRule, Error, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Role {
+    #[serde(default)]
+    pub inherits: Vec<String>,
+    #[serde(default)]
+    pub command_sets: Vec<String>,
+    #[serde(default)]
+    pub commands: Vec<CommandRule>,
+    #[serde(default)]
+    pub deny: Vec<CommandPattern>,
+}
+
+pub(crate) struct Roles<'a> {
+    command_sets: &'a BTreeMap<String, Vec<CommandRule>>,
+    roles: &'a BTreeMap<String, Role>,
+}
+
+impl<'a> Roles<'a> {
+    pub(crate) fn new(
+        command_sets: &'a BTreeMap<String, Vec<CommandRule>>,
+        roles: &'a BTreeMap<String, Role>,
+    ) -> Self {
+        Roles {
+            command_sets,
+            roles,
+        }
+    }
+
+    /// Check that all the roles resolve, even those no client uses.
+    pub(crate) fn validate(&self) -> Result<()> {
+        for name in self.roles.keys() {
+            self.collect(name, &mut Vec::new(), &mut Vec::new(), &mut Vec::new())?;
+        }
+        Ok(())
+    }
+
+    /// Append the commands granted and the patterns denied by the roles of a
+    /// client to its own.
+    pub(crate) fn expand(&self, client: &mut AuthorizedClient) -> Result<()> {
+        let mut commands = Vec::new();
+        let mut deny = Vec::new();
+        for name in &client.roles {
+            self.collect(name, &mut Vec::new(), &mut commands, &mut deny)
+                .map_err(|e| match e {
+                    Error::InvalidPolicy(msg) => {
+                        Error::InvalidPolicy(format!("client '{}': {}", client.name, msg))
+                    }
+                    e => e,
+                })?;
+        }
+        client.commands.extend(commands);
+        client.deny.extend(deny);
+        Ok(())
+    }
+
+    fn collect(
+        &self,
+        name: &str,
+        stack: &mut Vec<&'a str>,
+        commands: &mut Vec<CommandRule>,
+        deny: &mut Vec<CommandPattern>,
+    ) -> Result<()> {
+        let (name, role) = self
+            .roles
+            .get_key_value(name)
+            .ok_or_else(|| Error::InvalidPolicy(format!("unknown role '{}'", name)))?;
+        if stack.contains(&name.as_str()) {
+            stack.push(name);
+            return Err(Error::InvalidPolicy(format!(
+                "cycle in roles: {}",
+                stack.join(" -> ")
+            )));
+        }
+        stack.push(name);
+        for set in &role.command_sets {
+            let set = self.command_sets.get(set).ok_or_else(|| {
+                Error::InvalidPolicy(format!("unknown command set '{}' in role '{}'", set, name))
+            })?;
+            commands.extend(set.iter().cloned());
+        }
+        commands.extend(role.commands.iter().cloned());
+        deny.extend(role.deny.iter().cloned());
+        for parent in &role.inherits {
+            self.collect(parent, stack, commands, deny)?;
+        }
+        stack.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Authorizations, Error};
+
+    const CLIENT: &str = r#"
+        [[clients]]
+        name = "Client 1"
+        pub_key = """{}"""
+        roles = ["web"]
+        commands = ["date"]
+    "#;
+
+    fn load(roles: &str) -> crate::Result<Authorizations> {
+        let pub_key = include_str!("../../tests/server.pubkey.pem");
+        let client = CLIENT.replace("{}", pub_key);
+        Ok(toml::from_str(&format!("{}\n{}", client, roles))?)
+    }
+
+    #[test]
+    fn expand_roles() {
+        let authorizations = load(
+            r#"
+            [command_sets]
+            logs = ["journalctl -n 100", "dmesg"]
+
+            [roles.base]
+            commands = ["uptime"]
+
+            [roles.web]
+            inherits = ["base"]
+            command_sets = ["logs"]
+            commands = ["systemctl restart nginx"]
+            deny = ["dmesg"]
+            "#,
+        )
+        .unwrap();
+        let client = &authorizations.clients()[0];
+        let commands = client
+            .commands
+            .iter()
+            .map(|rule| rule.pattern.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            vec![
+                "date",
+                "journalctl -n 100",
+                "dmesg",
+                "systemctl restart nginx",
+                "uptime"
+            ]
+        );
+        assert!(client.is_allowed("uptime"));
+        assert!(!client.is_allowed("dmesg"));
+    }
+
+    #[test]
+    fn reject_invalid_roles() {
+        let result = load(
+            r#"
+            [roles.web]
+            inherits = ["base"]
+
+            [roles.base]
+            inherits = ["web"]
+            "#,
+        );
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("cycle in roles: base -> web -> base"));
+        let result = load("[roles.web]\ninherits = [\"admin\"]");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("unknown role 'admin'"));
+        let result = load("[roles.web]\n[roles.unused]\ncommand_sets = [\"logs\"]");
+        assert!(matches!(result, Err(Error::Toml(_))));
+    }
+}
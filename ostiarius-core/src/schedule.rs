@@ -0,0 +1,183 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Time windows during which a command is allowed.
+//!
+//! A schedule lists windows, each one opening on some days of the week between
+//! two times of the day, in a given timezone. A window whose end is before its
+//! start spans midnight. No command is allowed on blackout dates.
+
+use crate::{Error, Result};
+use chrono::{
+    DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer};
+
+/// How far ahead to look for the next opening of a schedule, in days.
+const LOOKAHEAD_DAYS: i64 = 400;
+
+/// Source of the current time.
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+fn all_days() -> Vec<Weekday> {
+    vec![
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ]
+}
+
+fn deserialize_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<NaiveTime, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&value, "%H:%M").map_err(serde::de::Error::custom)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimeWindow {
+    #[serde(default = "all_days")]
+    pub days: Vec<Weekday>,
+    #[serde(default, deserialize_with = "deserialize_time")]
+    pub from: NaiveTime,
+    #[serde(default, deserialize_with = "deserialize_time")]
+    pub to: NaiveTime,
+}
+
+impl TimeWindow {
+    fn opens_on(&self, date: NaiveDate) -> bool {
+        self.days.contains(&date.weekday())
+    }
+
+    fn contains(&self, local: NaiveDateTime) -> bool {
+        let (date, time) = (local.date(), local.time());
+        if self.from < self.to {
+            self.opens_on(date) && time >= self.from && time < self.to
+        } else {
+            (self.opens_on(date) && time >= self.from)
+                || (self.opens_on(date - Duration::days(1)) && time < self.to)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Schedule {
+    #[serde(default = "default_timezone")]
+    pub timezone: Tz,
+    #[serde(default)]
+    pub windows: Vec<TimeWindow>,
+    #[serde(default)]
+    pub blackout: Vec<NaiveDate>,
+}
+
+fn default_timezone() -> Tz {
+    Tz::UTC
+}
+
+impl Schedule {
+    pub fn allows(&self, time: DateTime<Utc>) -> bool {
+        let local = time.with_timezone(&self.timezone).naive_local();
+        !self.blackout.contains(&local.date())
+            && (self.windows.is_empty() || self.windows.iter().any(|w| w.contains(local)))
+    }
+
+    /// Earliest time after `time` at which the schedule allows a command.
+    pub fn next_allowed(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let today = time.with_timezone(&self.timezone).naive_local().date();
+        (0..LOOKAHEAD_DAYS)
+            .map(|n| today + Duration::days(n))
+            .flat_map(|date| {
+                let midnight = NaiveTime::from_hms_opt(0, 0, 0).unwrap_or_default();
+                std::iter::once(midnight)
+                    .chain(self.windows.iter().map(|w| w.from))
+                    .map(move |start| date.and_time(start))
+                    .collect::<Vec<_>>()
+            })
+            .filter_map(|local| self.timezone.from_local_datetime(&local).earliest())
+            .map(|start| start.with_timezone(&Utc))
+            .filter(|start| *start > time && self.allows(*start))
+            .min()
+    }
+
+    /// Fail with `Error::OutsideTimeWindow` if the schedule does not allow a
+    /// command at the given time.
+    pub fn check(&self, time: DateTime<Utc>) -> Result<()> {
+        if self.allows(time) {
+            Ok(())
+        } else {
+            Err(Error::OutsideTimeWindow(self.next_allowed(time)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule() -> Schedule {
+        toml::from_str(
+            r#"
+            timezone = "Europe/Paris"
+            blackout = ["2022-12-24"]
+            windows = [
+                { days = ["sat", "sun"] },
+                { days = ["mon", "tue", "wed", "thu", "fri"], from = "22:00", to = "06:00" },
+            ]
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn allows_in_windows() {
+        let schedule = schedule();
+        // Wednesday 2022-12-14, 23:30 in Paris.
+        assert!(schedule.allows(utc("2022-12-14T22:30:00Z")));
+        // Thursday 2022-12-15, 05:59 in Paris, in the window opened on Wednesday.
+        assert!(schedule.allows(utc("2022-12-15T04:59:00Z")));
+        assert!(!schedule.allows(utc("2022-12-15T05:00:00Z")));
+        // Saturday 2022-12-17, all day.
+        assert!(schedule.allows(utc("2022-12-17T12:00:00Z")));
+        // Saturday 2022-12-24, blackout date.
+        assert!(!schedule.allows(utc("2022-12-24T12:00:00Z")));
+    }
+
+    #[test]
+    fn next_allowed_time() {
+        let schedule = schedule();
+        let result = schedule.check(utc("2022-12-15T10:00:00Z"));
+        assert!(matches!(
+            result,
+            Err(Error::OutsideTimeWindow(Some(next))) if next == utc("2022-12-15T21:00:00Z")
+        ));
+        // Friday 2022-12-23, 07:00 in Paris.
+        let next = schedule.next_allowed(utc("2022-12-23T06:00:00Z"));
+        assert_eq!(next, Some(utc("2022-12-23T21:00:00Z")));
+        // Saturday 2022-12-24, 00:30 in Paris, a blackout date: wait for Sunday.
+        let next = schedule.next_allowed(utc("2022-12-23T23:30:00Z"));
+        assert_eq!(next, Some(utc("2022-12-24T23:00:00Z")));
+    }
+}
@@ -0,0 +1,231 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::{
+    frame::{Frame, FrameKind},
+    problem::Reason,
+    Authorization, Error, Request, Result,
+};
+use nix::sys::termios::{self, BaudRate, SetArg, SpecialCharacterIndices};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use url::Url;
+
+pub const DEFAULT_BAUD_RATE: u32 = 115200;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialAddress {
+    pub path: PathBuf,
+    pub baud_rate: u32,
+}
+
+impl std::str::FromStr for SerialAddress {
+    type Err = Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let url = Url::parse(s)?;
+        if url.scheme() != "serial" || url.path().is_empty() {
+            return Err(Error::InvalidUri(s.into()));
+        }
+        let baud_rate = url
+            .query_pairs()
+            .find_map(|(k, v)| if k == "baud" { Some(v) } else { None })
+            .map(|v| v.parse::<u32>())
+            .transpose()?
+            .unwrap_or(DEFAULT_BAUD_RATE);
+        let address = SerialAddress {
+            path: PathBuf::from(url.path()),
+            baud_rate,
+        };
+        Ok(address)
+    }
+}
+
+fn baud_rate(value: u32) -> Result<BaudRate> {
+    let rate = match value {
+        1200 => BaudRate::B1200,
+        2400 => BaudRate::B2400,
+        4800 => BaudRate::B4800,
+        9600 => BaudRate::B9600,
+        19200 => BaudRate::B19200,
+        38400 => BaudRate::B38400,
+        57600 => BaudRate::B57600,
+        115200 => BaudRate::B115200,
+        230400 => BaudRate::B230400,
+        _ => return Err(Error::InvalidBaudRate(value)),
+    };
+    Ok(rate)
+}
+
+/// Open a serial device in raw mode. Without timeout, reads block until data
+/// is available, otherwise they fail if no data is received in time (the
+/// timeout is rounded to tenths of seconds, up to 25.5 seconds).
+pub fn open<P: AsRef<Path>>(path: P, rate: u32, timeout: Option<Duration>) -> Result<File> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(nix::libc::O_NOCTTY)
+        .open(path)?;
+    let mut attrs = termios::tcgetattr(&file).map_err(std::io::Error::from)?;
+    termios::cfmakeraw(&mut attrs);
+    termios::cfsetspeed(&mut attrs, baud_rate(rate)?).map_err(std::io::Error::from)?;
+    let (vmin, vtime) = match timeout {
+        Some(timeout) => (0, (timeout.as_millis() / 100).clamp(1, 255) as u8),
+        None => (1, 0),
+    };
+    attrs.control_chars[SpecialCharacterIndices::VMIN as usize] = vmin;
+    attrs.control_chars[SpecialCharacterIndices::VTIME as usize] = vtime;
+    termios::tcsetattr(&file, SetArg::TCSANOW, &attrs).map_err(std::io::Error::from)?;
+    Ok(file)
+}
+
+/// Send an authorization request over a serial line and wait for the response.
+pub fn exchange<P: Read + Write>(port: &mut P, request: &Request) -> Result<Authorization> {
+    let frame = Frame::new(FrameKind::Request, serde_json::to_vec(request)?);
+    frame.write(port)?;
+    loop {
+        let frame = Frame::read(port)?;
+        match frame.kind {
+            FrameKind::Authorization => return Ok(serde_json::from_slice(&frame.payload)?),
+            FrameKind::Denied => return Err(Error::Unauthorized),
+            FrameKind::Error => {
+                let message = String::from_utf8_lossy(&frame.payload).into_owned();
+                return Err(Error::Transport(message));
+            }
+            FrameKind::Request => continue,
+        }
+    }
+}
+
+fn timed_out(error: &Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(error, Error::Io(e) if matches!(
+        e.kind(),
+        ErrorKind::UnexpectedEof | ErrorKind::TimedOut | ErrorKind::WouldBlock
+    ))
+}
+
+/// Wait for an authorization request on a serial line and answer it with the
+/// decision of `decide`.
+///
+/// A frame which is corrupted, or not received completely before the timeout
+/// of the line, is answered with an error frame, so the client does not wait
+/// in vain for an authorization.
+pub fn answer<P, F>(port: &mut P, decide: F) -> Result<Authorization>
+where
+    P: Read + Write,
+    F: FnOnce(&Request) -> Result<Authorization>,
+{
+    let frame = loop {
+        match Frame::sync(port) {
+            Err(e) if timed_out(&e) => continue,
+            result => result?,
+        }
+        match Frame::read_body(port) {
+            Ok(frame) if frame.kind == FrameKind::Request => break frame,
+            Ok(_) => continue,
+            Err(e @ Error::InvalidFrame(_)) => {
+                Frame::new(FrameKind::Error, b"invalid frame".to_vec()).write(port)?;
+                return Err(e);
+            }
+            Err(e) if timed_out(&e) => {
+                Frame::new(FrameKind::Error, b"incomplete frame".to_vec()).write(port)?;
+                return Err(Error::InvalidFrame("incomplete frame".to_string()));
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    let result = serde_json::from_slice::<Request>(&frame.payload)
+        .map_err(Error::from)
+        .and_then(|request| decide(&request));
+    let response = match &result {
+        Ok(authorization) => {
+            Frame::new(FrameKind::Authorization, serde_json::to_vec(authorization)?)
+        }
+        Err(Error::ApprovalRequired) => Frame::new(FrameKind::Denied, Vec::new()),
+        Err(e) if Reason::from(e) != Reason::InternalError => {
+            Frame::new(FrameKind::Denied, Vec::new())
+        }
+        Err(_) => Frame::new(FrameKind::Error, b"internal error".to_vec()),
+    };
+    response.write(port)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authorization::tests::{create_checker, create_requester};
+    use nix::{pty::openpty, unistd::ttyname};
+
+    #[test]
+    fn parse_address() {
+        let address = "serial:///dev/ttyUSB0?baud=9600"
+            .parse::<SerialAddress>()
+            .unwrap();
+        assert_eq!(address.path, PathBuf::from("/dev/ttyUSB0"));
+        assert_eq!(address.baud_rate, 9600);
+        let address = "serial:///dev/ttyS0".parse::<SerialAddress>().unwrap();
+        assert_eq!(address.baud_rate, DEFAULT_BAUD_RATE);
+        assert!("http://localhost".parse::<SerialAddress>().is_err());
+    }
+
+    #[test]
+    fn exchange_over_pty() {
+        let pty = openpty(None, None).unwrap();
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let server_path = ttyname(&pty.slave).unwrap();
+        let mut server_port = open(server_path, DEFAULT_BAUD_RATE, None).unwrap();
+        let handle = std::thread::spawn(move || {
+            let decide = |request: &Request| checker.check(request, None);
+            let granted = answer(&mut server_port, decide).map(|a| a.id);
+            let denied = answer(&mut server_port, decide).map(|a| a.id);
+            (granted, denied)
+        });
+        let mut client_port = File::from(pty.master);
+        let request = requester.make("Client 1", "date").unwrap();
+        let authorization = exchange(&mut client_port, &request).unwrap();
+        assert!(requester.check(&authorization).unwrap());
+        let request = requester.make("Client 1", "reboot").unwrap();
+        let result = exchange(&mut client_port, &request);
+        assert!(matches!(result, Err(Error::Unauthorized)));
+        let (granted, denied) = handle.join().unwrap();
+        assert_eq!(granted.unwrap(), authorization.id);
+        assert!(matches!(denied, Err(Error::CommandNotAllowed(_))));
+    }
+
+    #[test]
+    fn answer_invalid_frames() {
+        let pty = openpty(None, None).unwrap();
+        let server_path = ttyname(&pty.slave).unwrap();
+        let timeout = Some(Duration::from_millis(200));
+        let mut server_port = open(server_path, DEFAULT_BAUD_RATE, timeout).unwrap();
+        let handle = std::thread::spawn(move || {
+            let decide = |_: &Request| Err(Error::Unauthorized);
+            let corrupted = answer(&mut server_port, decide);
+            let truncated = answer(&mut server_port, decide);
+            (corrupted, truncated)
+        });
+        let mut client_port = File::from(pty.master);
+        let mut data = Vec::new();
+        Frame::new(FrameKind::Request, b"{}".to_vec())
+            .write(&mut data)
+            .unwrap();
+        data[5] ^= 0x01;
+        client_port.write_all(&data).unwrap();
+        let frame = Frame::read(&mut client_port).unwrap();
+        assert_eq!(frame.kind, FrameKind::Error);
+        client_port.write_all(&data[..4]).unwrap();
+        let frame = Frame::read(&mut client_port).unwrap();
+        assert_eq!(frame.kind, FrameKind::Error);
+        let (corrupted, truncated) = handle.join().unwrap();
+        assert!(matches!(corrupted, Err(Error::InvalidFrame(_))));
+        assert!(matches!(truncated, Err(Error::InvalidFrame(_))));
+    }
+}
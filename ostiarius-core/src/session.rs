@@ -0,0 +1,277 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::{matcher::argv, utils::glob_match, AuthorizedClient, Checker, Error, Requester};
+use crate::{Result, Signed};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+/// Commands a client may execute without further authorization, given as
+/// globs, for at most `duration` minutes. A session can not be opened if one of
+/// its globs may match a denied command, or a command whose rule the client can
+/// not enforce on its own (see `CommandRule::is_constrained`), nor for a client
+/// with a rate limit or a daily quota.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionPolicy {
+    pub commands: Vec<String>,
+    pub duration: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SessionRequest {
+    pub name: String,
+    pub duration: Option<u32>,
+    #[serde(with = "crate::wire::binary")]
+    pub challenge: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Session {
+    pub id: Uuid,
+    pub name: String,
+    pub commands: Vec<String>,
+    pub issued: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+    #[serde(with = "crate::wire::binary")]
+    pub token: String,
+    #[serde(default, with = "crate::wire::binary")]
+    pub signature: String,
+}
+
+impl Signed for Session {
+    fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    fn signature_mut(&mut self) -> &mut String {
+        &mut self.signature
+    }
+}
+
+/// Status of a session, as told by the server to a client checking whether it
+/// was revoked. It is bound to the nonce chosen by the client, so that an old
+/// status can not be replayed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SessionStatus {
+    pub id: Uuid,
+    pub nonce: Uuid,
+    pub active: bool,
+    #[serde(default, with = "crate::wire::binary")]
+    pub signature: String,
+}
+
+impl Signed for SessionStatus {
+    fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    fn signature_mut(&mut self) -> &mut String {
+        &mut self.signature
+    }
+}
+
+impl SessionStatus {
+    /// Whether the status answers the nonce of a client and tells that its
+    /// session is still active. Its signature must be verified separately.
+    pub fn confirms(&self, id: Uuid, nonce: Uuid) -> bool {
+        self.id == id && self.nonce == nonce && self.active
+    }
+}
+
+impl Session {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now >= self.expires
+    }
+
+    /// Whether the session covers a command, with its arguments separated by
+    /// single spaces, as matched by the server.
+    pub fn allows(&self, command: &str) -> bool {
+        let command = argv(command).join(" ");
+        self.commands
+            .iter()
+            .any(|pattern| glob_match(pattern, &command))
+    }
+}
+
+impl Requester {
+    pub fn make_session(&self, name: &str, duration: Option<u32>) -> Result<SessionRequest> {
+        let request = SessionRequest {
+            name: name.to_string(),
+            duration,
+            challenge: self.make_challenge()?,
+        };
+        Ok(request)
+    }
+
+    pub fn check_session(&self, session: &Session) -> Result<bool> {
+        Ok(self.verify(session)? && self.check_token(&session.token)?)
+    }
+}
+
+/// Check that a session covers no denied command, and that a client can
+/// enforce on its own the rules of the commands it may cover.
+fn check_session_policy(clients: &[&AuthorizedClient], policy: &SessionPolicy) -> Result<()> {
+    if let Some(client) = clients
+        .iter()
+        .find(|client| client.rate_limit.is_some() || client.daily_quota.is_some())
+    {
+        let message = format!("client '{}' is rate limited", client.name);
+        return Err(Error::SessionNotAllowed(message));
+    }
+    for glob in &policy.commands {
+        let denied = clients
+            .iter()
+            .flat_map(|client| client.deny.iter())
+            .find(|pattern| pattern.may_match_glob(glob));
+        if let Some(pattern) = denied {
+            let message = format!("'{}' covers denied command '{}'", glob, pattern);
+            return Err(Error::SessionNotAllowed(message));
+        }
+        let conflict = clients
+            .iter()
+            .flat_map(|client| client.commands.iter())
+            .find(|rule| rule.is_constrained() && rule.pattern.may_match_glob(glob));
+        if let Some(rule) = conflict {
+            let message = format!("'{}' covers constrained rule '{}'", glob, rule.pattern);
+            return Err(Error::SessionNotAllowed(message));
+        }
+    }
+    Ok(())
+}
+
+impl Checker {
+    pub fn open_session(
+        &self,
+        request: &SessionRequest,
+        source: Option<IpAddr>,
+    ) -> Result<Session> {
+        let challenge = self.decrypt_challenge(&request.challenge)?;
+        let clients = self.find_clients(&request.name, source)?;
+        let (client, policy) = clients
+            .iter()
+            .find_map(|client| client.session.as_ref().map(|policy| (*client, policy)))
+            .ok_or(Error::Unauthorized)?;
+        check_session_policy(&clients, policy)?;
+        let duration = request
+            .duration
+            .map_or(policy.duration, |d| d.min(policy.duration));
+        let issued = self.now();
+        let mut session = Session {
+            id: Uuid::new_v4(),
+            name: request.name.clone(),
+            commands: policy.commands.clone(),
+            issued,
+            expires: issued + Duration::minutes(duration.into()),
+            token: client.encrypt(&challenge)?,
+            signature: String::new(),
+        };
+        self.sign(&mut session)?;
+        Ok(session)
+    }
+
+    pub fn session_status(&self, id: Uuid, nonce: Uuid, active: bool) -> Result<SessionStatus> {
+        let mut status = SessionStatus {
+            id,
+            nonce,
+            active,
+            signature: String::new(),
+        };
+        self.sign(&mut status)?;
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authorization::tests::{create_checker, create_requester};
+    use crate::Authorizations;
+    use std::path::PathBuf;
+
+    #[test]
+    fn open_and_check_session() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make_session("Client 1", Some(60)).unwrap();
+        let session = checker.open_session(&request, None).unwrap();
+        assert!(requester.check_session(&session).unwrap());
+        assert_eq!(session.expires - session.issued, Duration::minutes(30));
+        assert!(session.allows("date -u"));
+        assert!(!session.allows("reboot"));
+        assert!(!session.is_expired(session.issued));
+        assert!(session.is_expired(session.expires));
+    }
+
+    #[test]
+    fn open_session_not_allowed() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make_session("Client 2", None).unwrap();
+        let result = checker.open_session(&request, "127.0.0.1".parse().ok());
+        assert!(matches!(result, Err(Error::Unauthorized)));
+    }
+
+    #[test]
+    fn open_constrained_session() {
+        let data_dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "..", "tests"].iter().collect();
+        let contents = std::fs::read_to_string(data_dir.join("authorizations.toml")).unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make_session("Client 1", None).unwrap();
+        for commands in [
+            r#""make *""#,
+            r#""apt-get *""#,
+            r#""/usr/bin/systemctl *""#,
+            r#""/usr/sbin/service ssh*""#,
+            r#""/usr/sbin/service * off""#,
+        ] {
+            let contents = contents.replacen(r#""date *""#, commands, 1);
+            let authorizations: Authorizations = toml::from_str(&contents).unwrap();
+            let checker = create_checker()
+                .unwrap()
+                .with_authorizations(authorizations);
+            let result = checker.open_session(&request, None);
+            assert!(matches!(result, Err(Error::SessionNotAllowed(_))));
+        }
+        let contents = contents.replacen(r#""date *""#, r#""date*""#, 1);
+        let authorizations: Authorizations = toml::from_str(&contents).unwrap();
+        let checker = create_checker()
+            .unwrap()
+            .with_authorizations(authorizations);
+        let session = checker.open_session(&request, None).unwrap();
+        assert!(session.allows("date  -u"));
+    }
+
+    #[test]
+    fn check_session_status() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let (id, nonce) = (Uuid::new_v4(), Uuid::new_v4());
+        let status = checker.session_status(id, nonce, true).unwrap();
+        assert!(requester.verify(&status).unwrap());
+        assert!(status.confirms(id, nonce));
+        assert!(!status.confirms(id, Uuid::new_v4()));
+        assert!(!status.confirms(Uuid::new_v4(), nonce));
+        let status = checker.session_status(id, nonce, false).unwrap();
+        assert!(!status.confirms(id, nonce));
+        let mut tampered = status;
+        tampered.active = true;
+        assert!(!requester.verify(&tampered).unwrap());
+        tampered.signature.clear();
+        assert!(!requester.verify(&tampered).unwrap());
+    }
+
+    #[test]
+    fn check_tampered_session() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make_session("Client 1", None).unwrap();
+        let mut session = checker.open_session(&request, None).unwrap();
+        session.commands.push("*".to_string());
+        assert!(!requester.check_session(&session).unwrap());
+    }
+}
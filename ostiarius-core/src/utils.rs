@@ -50,6 +50,34 @@ pub fn strip_trailing_newline(input: &mut String) -> &mut String {
     input
 }
 
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    backtrack = Some((bp, bt + 1));
+                    p = bp + 1;
+                    t = bt + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,4 +120,18 @@ mod tests {
         strip_trailing_newline(&mut s);
         assert_eq!(s, "");
     }
+
+    #[test]
+    fn glob_match_test() {
+        assert!(glob_match("date", "date"));
+        assert!(!glob_match("date", "date -u"));
+        assert!(glob_match("systemctl * nginx", "systemctl restart nginx"));
+        assert!(!glob_match("systemctl * nginx", "systemctl restart sshd"));
+        assert!(glob_match("ls *", "ls /etc"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("ls ?", "ls a"));
+        assert!(!glob_match("ls ?", "ls ab"));
+        assert!(glob_match("*a*b", "xxaxxb"));
+        assert!(!glob_match("*a*b", "xxaxxbc"));
+    }
 }
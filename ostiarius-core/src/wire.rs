@@ -0,0 +1,328 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Encoding of the messages exchanged between client and server.
+//!
+//! Messages are encoded either in JSON (`application/json`) or in CBOR
+//! (`application/cbor`, [RFC 8949]). The format of a request body is given by
+//! its `Content-Type` header, and the format of a response body is chosen
+//! from the `Accept` header of the request, JSON being the default.
+//!
+//! # Schema
+//!
+//! Messages are maps with text keys, in the order given below. Optional fields
+//! may be omitted. Types are given as `JSON / CBOR` when they differ:
+//!
+//! - *uuid*: string in hyphenated form / byte string of 16 bytes.
+//! - *timestamp*: RFC 3339 string, in UTC.
+//! - *binary*: base64 string (RFC 4648, with padding) / byte string.
+//! - *address*: IP address as a text string, e.g. `192.0.2.1` or `2001:db8::1`,
+//!   in both formats.
+//!
+//! `Request` (`POST /api/v1/authorizations`):
+//!
+//! | Field       | Type   | Description                                    |
+//! |-------------|--------|------------------------------------------------|
+//! | `name`      | string | Name of the client                             |
+//! | `command`   | string | Command to execute, or name of an action       |
+//! | `params`    | map    | Parameters of the action (optional)            |
+//! | `challenge` | binary | Random token encrypted with server public key  |
+//!
+//! `Authorization` (`GET /api/v1/authorizations/:id`):
+//!
+//! | Field       | Type      | Description                                 |
+//! |-------------|-----------|---------------------------------------------|
+//! | `id`        | uuid      | Identifier of the authorization             |
+//! | `timestamp` | timestamp | Time of grant                               |
+//! | `name`      | string    | Name of the client                          |
+//! | `command`   | string    | Authorized command, or name of an action    |
+//! | `params`    | map       | Parameters of the action (optional)         |
+//! | `argv`      | array     | Arguments rendered from the action          |
+//! | `token`     | binary    | Random token encrypted with client key      |
+//! | `source`    | address   | Address of the client, if known (optional)  |
+//! | `exec`      | map       | Execution context, if any (optional)        |
+//! | `approvals` | array     | Approvals by operators, if any (optional)   |
+//! | `signature` | binary    | Signature by the server                     |
+//!
+//! `BatchRequest` (`POST /api/v1/batches`) has the same fields as `Request`,
+//! except `command` is replaced by `commands`, an array of strings. The
+//! response is a `BatchAuthorization`, with the same fields as
+//! `Authorization`, except `command` is replaced by `decisions`, an array of
+//! maps with the fields `command` (string), `authorized` (boolean), `id`
+//! (uuid or null) and `exec` (map, optional).
+//!
+//! The `exec` map has the optional fields `user`, `group`, `cwd`, `executable`
+//! and `sha256` (strings), `env`, a map with the optional fields `allow` and
+//! `strip` (arrays of glob patterns) and `set` (map of strings), and `script`,
+//! a map with the fields `interpreter`, `body` and `sha256` (strings).
+//!
+//! `SessionRequest` (`POST /api/v1/sessions`) has the fields `name` (string),
+//! `duration` (integer, in minutes, or null) and `challenge` (binary). The
+//! response is a `Session`, with the fields `id` (uuid), `name` (string),
+//! `commands` (array of strings), `issued` and `expires` (timestamps), `token`
+//! and `signature` (binary).
+//!
+//! `Approval` (`POST /api/v1/approvals`) has the fields `request_id` (uuid),
+//! `operator` (string), `approved` (boolean), `timestamp` (timestamp) and
+//! `signature` (binary), the signature being made with the operator key.
+//! `GET /api/v1/approvals` returns an array of `PendingAuthorization`, with the
+//! fields `id` (uuid), `timestamp` (timestamp), `name` and `command` (strings),
+//! `params` (map of strings, optional), `challenge` (binary), `source`
+//! (address, optional), `required` (integer, number of approvals needed) and
+//! `approvals` (array of `Approval`).
+//!
+//! `ExplainRequest` (`POST /api/v1/policy/explain`) has the fields `operator`,
+//! `name` and `command` (strings), `source` (address, optional), `time`
+//! (timestamp, optional), `timestamp` (timestamp) and `signature` (binary),
+//! the signature being made with the operator key. The response is an
+//! `Explanation`, with the fields `name` and `command` (strings), `source`
+//! (address, optional), `time` (timestamp), `verdict` (`allowed`,
+//! `approval-required` or `denied`), `reason` (string, optional), `exec` (map,
+//! optional) and `steps`, an array of maps with the fields `rule` (string),
+//! `matched` (boolean) and `detail` (string, optional).
+//!
+//! Signatures are always computed over the JSON encoding of the message, with
+//! an empty `signature` field, whatever the format used on the wire.
+//!
+//! [RFC 8949]: https://www.rfc-editor.org/rfc/rfc8949
+
+use crate::{Error, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+pub const JSON: &str = "application/json";
+pub const CBOR: &str = "application/cbor";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Json,
+    Cbor,
+}
+
+fn media_type(value: &str) -> &str {
+    value.split(';').next().unwrap_or_default().trim()
+}
+
+impl Format {
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Format::Json => JSON,
+            Format::Cbor => CBOR,
+        }
+    }
+
+    pub fn from_content_type(value: &str) -> Option<Self> {
+        match media_type(value).to_ascii_lowercase().as_str() {
+            JSON => Some(Format::Json),
+            CBOR => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Select the format of a response from the value of an `Accept` header,
+    /// honoring quality values. Defaults to JSON.
+    pub fn from_accept(value: &str) -> Self {
+        let mut best: Option<(Format, f32)> = None;
+        for item in value.split(',') {
+            let quality = item
+                .split(';')
+                .skip(1)
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let format = match media_type(item).to_ascii_lowercase().as_str() {
+                JSON | "application/*" | "*/*" => Format::Json,
+                CBOR => Format::Cbor,
+                _ => continue,
+            };
+            match best {
+                Some((_, q)) if q >= quality => {}
+                _ if quality > 0.0 => best = Some((format, quality)),
+                _ => {}
+            }
+        }
+        best.map(|(format, _)| format).unwrap_or_default()
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            Format::Json => Ok(serde_json::to_vec(value)?),
+            Format::Cbor => {
+                let mut data = Vec::new();
+                ciborium::ser::into_writer(value, &mut data)
+                    .map_err(|e| Error::Cbor(e.to_string()))?;
+                Ok(data)
+            }
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        match self {
+            Format::Json => Ok(serde_json::from_slice(data)?),
+            Format::Cbor => ciborium::de::from_reader(data).map_err(|e| Error::Cbor(e.to_string())),
+        }
+    }
+}
+
+/// Serialization of base64-encoded binary data: kept as text in human
+/// readable formats, converted to raw bytes otherwise.
+pub mod binary {
+    use openssl::base64;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(value)
+        } else {
+            let data = base64::decode_block(value).map_err(serde::ser::Error::custom)?;
+            serializer.serialize_bytes(&data)
+        }
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> de::Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a byte string")
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut data = Vec::new();
+            while let Some(byte) = seq.next_element()? {
+                data.push(byte);
+            }
+            Ok(data)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        if deserializer.is_human_readable() {
+            String::deserialize(deserializer)
+        } else {
+            let data = deserializer.deserialize_byte_buf(BytesVisitor)?;
+            Ok(base64::encode_block(&data))
+        }
+    }
+}
+
+/// Serialization of an optional IP address as a string, in all formats.
+/// Otherwise, non human readable formats encode it as an enum of bytes.
+pub mod address {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::net::IpAddr;
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<IpAddr>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(address) => serializer.collect_str(address),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<IpAddr>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|address| address.parse().map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authorization::tests::{create_checker, create_requester};
+    use crate::{Authorization, ExplainRequest, Request};
+    use ciborium::value::Value;
+    use std::net::IpAddr;
+
+    #[test]
+    fn negotiate() {
+        assert_eq!(
+            Format::from_content_type("application/cbor"),
+            Some(Format::Cbor)
+        );
+        assert_eq!(
+            Format::from_content_type("application/json; charset=utf-8"),
+            Some(Format::Json)
+        );
+        assert_eq!(Format::from_content_type("text/plain"), None);
+        assert_eq!(Format::from_accept(""), Format::Json);
+        assert_eq!(Format::from_accept("*/*"), Format::Json);
+        assert_eq!(Format::from_accept("application/cbor"), Format::Cbor);
+        assert_eq!(
+            Format::from_accept("application/json;q=0.5, application/cbor"),
+            Format::Cbor
+        );
+        assert_eq!(
+            Format::from_accept("application/cbor;q=0.1, application/json;q=0.9"),
+            Format::Json
+        );
+        assert_eq!(Format::from_accept("text/html"), Format::Json);
+    }
+
+    #[test]
+    fn encode_and_decode() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 1", "date").unwrap();
+        let json = Format::Json.encode(&request).unwrap();
+        let cbor = Format::Cbor.encode(&request).unwrap();
+        assert!(cbor.len() < json.len());
+        let decoded: Request = Format::Cbor.decode(&cbor).unwrap();
+        assert_eq!(decoded.challenge, request.challenge);
+        let authorization = checker.check(&decoded, None).unwrap();
+        let cbor = Format::Cbor.encode(&authorization).unwrap();
+        let decoded: Authorization = Format::Cbor.decode(&cbor).unwrap();
+        assert!(requester.check(&decoded).unwrap());
+    }
+
+    #[test]
+    fn decode_source() {
+        let checker = create_checker().unwrap();
+        let requester = create_requester().unwrap();
+        let request = requester.make("Client 2", "uname -a").unwrap();
+        let source = "127.0.0.1".parse::<IpAddr>().ok();
+        let authorization = checker.check(&request, source).unwrap();
+        let cbor = Format::Cbor.encode(&authorization).unwrap();
+        let value: Value = ciborium::de::from_reader(cbor.as_slice()).unwrap();
+        let field = value
+            .as_map()
+            .unwrap()
+            .iter()
+            .find(|(key, _)| key.as_text() == Some("source"))
+            .map(|(_, value)| value.clone());
+        assert_eq!(field, Some(Value::Text("127.0.0.1".to_string())));
+        // Map of 5 pairs of short text strings, written by hand.
+        let mut cbor = vec![0xa5];
+        for (key, value) in [
+            ("operator", "Operator 1"),
+            ("name", "Client 2"),
+            ("command", "uname -a"),
+            ("source", "::1"),
+            ("timestamp", "2022-12-15T10:00:00Z"),
+        ] {
+            for text in [key, value] {
+                cbor.push(0x60 + text.len() as u8);
+                cbor.extend_from_slice(text.as_bytes());
+            }
+        }
+        let decoded: ExplainRequest = Format::Cbor.decode(&cbor).unwrap();
+        assert_eq!(decoded.source, "::1".parse().ok());
+    }
+}
@@ -4,12 +4,14 @@
 // SPDX-License-Identifier: MIT
 //
 
-use ostiarius_core::Checker;
+use crate::models;
+use ipnet::IpNet;
 use std::net::IpAddr;
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub address: IpAddr,
     pub port: u16,
-    pub checker: Checker,
+    pub checker: models::SharedChecker,
+    pub trusted_proxies: Vec<IpNet>,
 }
@@ -0,0 +1,102 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::http::{
+    credential::Signer,
+    problem::ApiError,
+    wire::{Accept, Wire},
+    ApiContext,
+};
+use crate::logging;
+use crate::models::{prune_approvals, Pending};
+use axum::{extract::Extension, http::StatusCode, response::IntoResponse, routing::get, Router};
+use ostiarius_core::{Approval, Error};
+
+/// List the requests waiting for approval, for operators only.
+async fn approvals_index(
+    accept: Accept,
+    signer: Signer,
+    Extension(ctx): Extension<ApiContext>,
+) -> std::result::Result<impl IntoResponse, ApiError> {
+    signer.operator(&*ctx.checker().await)?;
+    let mut approvals = ctx.approvals.lock().await;
+    prune_approvals(&mut approvals, chrono::offset::Utc::now());
+    let pending = approvals
+        .values()
+        .filter_map(|pending| match pending {
+            Pending::Waiting(pending) => Some(pending.clone()),
+            Pending::Denied(_) => None,
+        })
+        .collect::<Vec<_>>();
+    Ok(accept.encode(pending))
+}
+
+async fn approvals_create(
+    Wire(approval): Wire<Approval>,
+    Extension(ctx): Extension<ApiContext>,
+) -> StatusCode {
+    let checker = ctx.checker().await;
+    let id = approval.request_id;
+    let mut approvals = ctx.approvals.lock().await;
+    prune_approvals(&mut approvals, chrono::offset::Utc::now());
+    let mut pending = match approvals.get(&id) {
+        Some(Pending::Waiting(pending)) => pending.clone(),
+        Some(Pending::Denied(_)) => return StatusCode::CONFLICT,
+        None => return StatusCode::NOT_FOUND,
+    };
+    match checker.verify_approval(&pending, &approval) {
+        Err(Error::Unauthorized) => return StatusCode::FORBIDDEN,
+        Err(Error::DuplicateApproval(_)) => return StatusCode::CONFLICT,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+        Ok(_) => {}
+    }
+    let operator = approval.operator.clone();
+    if !approval.approved {
+        tracing::info!(
+            "Operator '{}' denied authorization {} for client '{}'",
+            &operator,
+            &id,
+            &pending.name
+        );
+        pending.approvals.push(approval);
+        approvals.insert(id, Pending::Denied(pending));
+        ctx.decided.notify_waiters();
+        return StatusCode::NO_CONTENT;
+    }
+    let authorization = match checker.approve(&mut pending, approval) {
+        Err(Error::Unauthorized) => return StatusCode::FORBIDDEN,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+        Ok(a) => a,
+    };
+    tracing::info!(
+        "Operator '{}' approved authorization {} for client '{}' ({}/{})",
+        &operator,
+        &id,
+        &pending.name,
+        pending.approvals.len(),
+        pending.required
+    );
+    match authorization {
+        Some(authorization) => {
+            logging::granted(&authorization);
+            ctx.database.lock().await.insert(id, authorization);
+            approvals.remove(&id);
+            ctx.decided.notify_waiters();
+            StatusCode::NO_CONTENT
+        }
+        None => {
+            approvals.insert(id, Pending::Waiting(pending));
+            StatusCode::ACCEPTED
+        }
+    }
+}
+
+pub fn router() -> Router {
+    Router::new().route(
+        "/api/v1/approvals",
+        get(approvals_index).post(approvals_create),
+    )
+}
@@ -4,25 +4,43 @@
 // SPDX-License-Identifier: MIT
 //
 
-use crate::http::ApiContext;
+use crate::http::{
+    problem::ApiError,
+    source::Source,
+    wire::{Accept, Wire},
+    ApiContext,
+};
+use crate::logging;
+use crate::models::{prune_approvals, Pending};
 use axum::{
     extract::{Extension, Path, Query},
     http::StatusCode,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     routing::get,
-    Json, Router,
+    Router,
 };
-use ostiarius_core::{Authorization, Error, Request};
+use ostiarius_core::{problem::Reason, Error, Request};
 use serde::Deserialize;
+use std::time::Duration;
+use tokio::time::Instant;
 use uuid::Uuid;
 
+/// Maximum time to wait for the decision about a pending request, in seconds.
+const MAX_WAIT: u64 = 60;
+
 #[derive(Debug, Deserialize, Default)]
 struct Pagination {
     pub offset: Option<usize>,
     pub limit: Option<usize>,
 }
 
+#[derive(Debug, Deserialize, Default)]
+struct Wait {
+    pub wait: Option<u64>,
+}
+
 async fn authorizations_index(
+    accept: Accept,
     pagination: Option<Query<Pagination>>,
     Extension(ctx): Extension<ApiContext>,
 ) -> impl IntoResponse {
@@ -34,36 +52,77 @@ async fn authorizations_index(
         .take(pagination.limit.unwrap_or(usize::MAX))
         .cloned()
         .collect::<Vec<_>>();
-    Json(authorizations)
+    accept.encode(authorizations)
 }
 
 async fn authorizations_get(
+    accept: Accept,
     Path(id): Path<Uuid>,
+    wait: Option<Query<Wait>>,
     Extension(ctx): Extension<ApiContext>,
-) -> std::result::Result<Json<Authorization>, StatusCode> {
-    let authorizations = ctx.database.lock().await;
-    let authorization = authorizations.get(&id).ok_or(StatusCode::NOT_FOUND)?;
-    Ok(Json(authorization.clone()))
+) -> std::result::Result<Response, StatusCode> {
+    let Query(wait) = wait.unwrap_or_default();
+    let wait = Duration::from_secs(wait.wait.unwrap_or(0).min(MAX_WAIT));
+    let deadline = Instant::now() + wait;
+    loop {
+        let decided = ctx.decided.notified();
+        {
+            let approvals = ctx.approvals.lock().await;
+            let authorizations = ctx.database.lock().await;
+            if let Some(authorization) = authorizations.get(&id) {
+                return Ok(accept.encode(authorization.clone()).into_response());
+            }
+            match approvals.get(&id) {
+                Some(Pending::Waiting(_)) => {}
+                Some(Pending::Denied(_)) => {
+                    return Ok(ApiError::from(Reason::ApprovalDenied).into_response())
+                }
+                None => return Err(StatusCode::NOT_FOUND),
+            }
+        }
+        if tokio::time::timeout_at(deadline, decided).await.is_err() {
+            return Ok((StatusCode::ACCEPTED, accept.encode(id)).into_response());
+        }
+    }
 }
 
 async fn authorizations_create(
-    Json(request): Json<Request>,
+    accept: Accept,
+    Source(source): Source,
+    Wire(request): Wire<Request>,
     Extension(ctx): Extension<ApiContext>,
-) -> std::result::Result<impl IntoResponse, StatusCode> {
-    let authorization = match ctx.checker.check(&request) {
-        Err(ref e) if matches!(e, Error::Unauthorized) => return Err(StatusCode::FORBIDDEN),
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+) -> std::result::Result<impl IntoResponse, ApiError> {
+    let checker = ctx.checker().await;
+    checker.throttle(
+        &mut *ctx.limits.lock().await,
+        &request.name,
+        source,
+        &request.command,
+        request.params.as_ref(),
+    )?;
+    let result = checker.check(&request, source);
+    let authorization = match result {
+        Err(Error::ApprovalRequired) => {
+            let pending = checker.defer(&request, source)?;
+            let id = pending.id;
+            let mut approvals = ctx.approvals.lock().await;
+            prune_approvals(&mut approvals, pending.timestamp);
+            tracing::info!(
+                "Authorization {} for client '{}' waiting for approval",
+                &id,
+                &pending.name
+            );
+            approvals.insert(id, Pending::Waiting(pending));
+            return Ok((StatusCode::ACCEPTED, accept.encode(id)));
+        }
+        Err(e) => return Err(e.into()),
         Ok(a) => a,
     };
     let id = authorization.id;
     let mut authorizations = ctx.database.lock().await;
-    tracing::info!(
-        "Granted authorization {} to client '{}'",
-        &id,
-        &authorization.name
-    );
+    logging::granted(&authorization);
     authorizations.insert(authorization.id, authorization);
-    Ok((StatusCode::CREATED, Json(id)))
+    Ok((StatusCode::CREATED, accept.encode(id)))
 }
 
 pub fn router() -> Router {
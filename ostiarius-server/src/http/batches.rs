@@ -0,0 +1,42 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::http::{
+    problem::ApiError,
+    source::Source,
+    wire::{Accept, Wire},
+    ApiContext,
+};
+use crate::logging;
+use axum::{extract::Extension, http::StatusCode, response::IntoResponse, routing::post, Router};
+use ostiarius_core::BatchRequest;
+
+async fn batches_create(
+    accept: Accept,
+    Source(source): Source,
+    Wire(request): Wire<BatchRequest>,
+    Extension(ctx): Extension<ApiContext>,
+) -> std::result::Result<impl IntoResponse, ApiError> {
+    let checker = ctx.checker().await;
+    checker.throttle_batch(
+        &mut *ctx.limits.lock().await,
+        &request.name,
+        source,
+        &request.commands,
+    )?;
+    let batch = checker.check_batch(&request, source)?;
+    let granted = checker.split_batch(&batch)?;
+    let mut authorizations = ctx.database.lock().await;
+    for authorization in granted {
+        logging::granted(&authorization);
+        authorizations.insert(authorization.id, authorization);
+    }
+    Ok((StatusCode::CREATED, accept.encode(batch)))
+}
+
+pub fn router() -> Router {
+    Router::new().route("/api/v1/batches", post(batches_create))
+}
@@ -0,0 +1,64 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+    http::header,
+};
+use ostiarius_core::{Checker, Credential, Error, Result};
+
+/// Credential given in the `Authorization` header of a request, if any, with
+/// the method and the path of the request it must be bound to.
+#[derive(Debug, Clone)]
+pub struct Signer {
+    credential: Option<Credential>,
+    method: String,
+    path: String,
+}
+
+impl Signer {
+    /// Check that the request was sent by an operator, and get its name.
+    pub fn operator(&self, checker: &Checker) -> Result<String> {
+        let credential = self.credential.as_ref().ok_or(Error::Unauthorized)?;
+        let operator = checker.verify_operator(credential, &self.method, &self.path)?;
+        Ok(operator.name.clone())
+    }
+
+    /// Check that the request was sent by the client with the given name.
+    pub fn client(&self, checker: &Checker, name: &str) -> Result<()> {
+        match &self.credential {
+            Some(credential) if credential.name == name => {
+                checker.verify_client(credential, &self.method, &self.path)
+            }
+            _ => Err(Error::Unauthorized),
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.credential
+            .as_ref()
+            .map(|credential| credential.name.as_str())
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Signer {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> std::result::Result<Self, Self::Rejection> {
+        let credential = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| Credential::from_header(value).ok());
+        Ok(Signer {
+            credential,
+            method: req.method().to_string(),
+            path: req.uri().path().to_string(),
+        })
+    }
+}
@@ -0,0 +1,32 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::http::{
+    wire::{Accept, Encoded},
+    ApiContext,
+};
+use axum::{extract::Extension, http::StatusCode, routing::get, Router};
+use ostiarius_core::ServerInfo;
+
+async fn info_get(
+    accept: Accept,
+    Extension(ctx): Extension<ApiContext>,
+) -> std::result::Result<Encoded<ServerInfo>, StatusCode> {
+    let info = ctx
+        .checker()
+        .await
+        .public_key()
+        .and_then(|key| ServerInfo::new(env!("CARGO_PKG_VERSION"), &key))
+        .map_err(|e| {
+            tracing::error!("Failed to get server public key: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(accept.encode(info))
+}
+
+pub fn router() -> Router {
+    Router::new().route("/api/v1/info", get(info_get))
+}
@@ -4,47 +4,239 @@
 // SPDX-License-Identifier: MIT
 //
 
+mod approvals;
 mod authorizations;
+mod batches;
+mod credential;
 mod index;
+mod info;
+mod policy;
+mod problem;
+mod sessions;
+mod source;
+mod wire;
 
 use crate::config::Config;
-use crate::models;
+use crate::{logging, models};
 use anyhow::Context;
 use axum::{extract::Extension, Router};
+use ipnet::IpNet;
 use ostiarius_core::Checker;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::sync::Notify;
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(Clone)]
 struct ApiContext {
-    checker: Arc<Checker>,
+    checker: models::SharedChecker,
     database: models::Database,
+    sessions: models::Sessions,
+    approvals: models::Approvals,
+    limits: models::Limits,
+    decided: Arc<Notify>,
+    trusted_proxies: Arc<Vec<IpNet>>,
 }
 
-pub async fn serve(config: Config, database: models::Database) -> anyhow::Result<()> {
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG")
-                .unwrap_or_else(|_| "ostiarius_server=info,tower_http=info".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-    let addr = SocketAddr::new(config.address, config.port);
-    let api_context = ApiContext {
-        checker: Arc::new(config.checker),
-        database,
-    };
+impl ApiContext {
+    /// Get the checker enforcing the current authorizations.
+    async fn checker(&self) -> Arc<Checker> {
+        self.checker.read().await.clone()
+    }
+}
+
+/// Routes of the API, sharing the given context.
+fn app(api_context: ApiContext) -> Router {
     let service = ServiceBuilder::new().layer(Extension(api_context));
-    let app = Router::new()
+    Router::new()
         .merge(index::router())
+        .merge(approvals::router())
         .merge(authorizations::router())
+        .merge(batches::router())
+        .merge(info::router())
+        .merge(policy::router())
+        .merge(sessions::router())
         .layer(service)
-        .layer(TraceLayer::new_for_http());
+}
+
+pub async fn serve(
+    config: Config,
+    database: models::Database,
+    sessions: models::Sessions,
+    approvals: models::Approvals,
+    limits: models::Limits,
+) -> anyhow::Result<()> {
+    logging::init();
+    let addr = SocketAddr::new(config.address, config.port);
+    let api_context = ApiContext {
+        checker: config.checker,
+        database,
+        sessions,
+        approvals,
+        limits,
+        decided: Arc::new(Notify::new()),
+        trusted_proxies: Arc::new(config.trusted_proxies),
+    };
+    let app = app(api_context).layer(TraceLayer::new_for_http());
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .context("failed to run HTTP server")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{Body, HttpBody},
+        extract::ConnectInfo,
+        http::{header, Method, Request, StatusCode},
+        response::Response,
+    };
+    use ostiarius_core::{
+        Authorizations, Credential, Requester, RsaPrivateKey, SessionStatus, Signed,
+    };
+    use serde::Serialize;
+    use std::path::PathBuf;
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    fn data_dir() -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "..", "tests"].iter().collect()
+    }
+
+    fn key(name: &str) -> RsaPrivateKey {
+        let path = data_dir().join(name);
+        RsaPrivateKey::from_uri(&format!("file://{}", path.display())).unwrap()
+    }
+
+    fn requester(name: &str) -> Requester {
+        let path = data_dir().join(name);
+        Requester::new(
+            &format!("file://{}", path.display()),
+            data_dir().join("server.pubkey.pem"),
+        )
+        .unwrap()
+    }
+
+    pub(super) fn context(trusted_proxies: Vec<IpNet>) -> ApiContext {
+        let authorizations = Authorizations::from_file(data_dir().join("authorizations.toml"));
+        let path = data_dir().join("server.privkey.pem");
+        let uri = format!("file://{}?password=1234", path.display());
+        let checker = Checker::new(&uri, authorizations.unwrap()).unwrap();
+        ApiContext {
+            checker: Arc::new(RwLock::new(Arc::new(checker))),
+            database: Default::default(),
+            sessions: Default::default(),
+            approvals: Default::default(),
+            limits: Default::default(),
+            decided: Arc::new(Notify::new()),
+            trusted_proxies: Arc::new(trusted_proxies),
+        }
+    }
+
+    fn signed(method: Method, path: &str, signer: Option<(&str, &str)>) -> Request<Body> {
+        let mut builder = Request::builder()
+            .method(method.clone())
+            .uri(path)
+            .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))));
+        if let Some((name, key_name)) = signer {
+            let mut credential = Credential::new(name, method.as_str(), path);
+            credential.sign(&key(key_name)).unwrap();
+            builder = builder.header(header::AUTHORIZATION, credential.to_header().unwrap());
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    fn post<T: Serialize>(path: &str, body: &T) -> Request<Body> {
+        Request::builder()
+            .method(Method::POST)
+            .uri(path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))))
+            .body(Body::from(serde_json::to_vec(body).unwrap()))
+            .unwrap()
+    }
+
+    async fn send(ctx: &ApiContext, request: Request<Body>) -> Response {
+        app(ctx.clone()).oneshot(request).await.unwrap()
+    }
+
+    async fn session_status(ctx: &ApiContext, id: Uuid, nonce: Uuid) -> SessionStatus {
+        let path = format!("/api/v1/sessions/{}?nonce={}", id, nonce);
+        let res = send(ctx, signed(Method::GET, &path, None)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let mut body = res.into_body();
+        let mut data = Vec::new();
+        while let Some(chunk) = body.data().await {
+            data.extend_from_slice(&chunk.unwrap());
+        }
+        serde_json::from_slice(&data).unwrap()
+    }
+
+    #[tokio::test]
+    async fn revoke_session() {
+        let ctx = context(Vec::new());
+        let session = requester("client1.privkey.pem")
+            .make_session("Client 1", None)
+            .unwrap();
+        let res = send(&ctx, post("/api/v1/sessions", &session)).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let id = *ctx.sessions.lock().await.keys().next().unwrap();
+        let path = format!("/api/v1/sessions/{}", id);
+
+        let index = "/api/v1/sessions";
+        let res = send(&ctx, signed(Method::GET, index, None)).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let client = Some(("Client 1", "client1.privkey.pem"));
+        let res = send(&ctx, signed(Method::GET, index, client)).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let operator = Some(("Operator 1", "operator1.privkey.pem"));
+        let res = send(&ctx, signed(Method::GET, index, operator)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let requester = requester("client1.privkey.pem");
+        let nonce = Uuid::from_u128(1);
+        let status = session_status(&ctx, id, nonce).await;
+        assert!(requester.verify(&status).unwrap());
+        assert!(status.confirms(id, nonce));
+        let res = send(&ctx, signed(Method::GET, &path, None)).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let res = send(&ctx, signed(Method::DELETE, &path, None)).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let other = Some(("Client 2", "client2.privkey.pem"));
+        let res = send(&ctx, signed(Method::DELETE, &path, other)).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        // A credential is bound to the path it was made for.
+        let mut stolen = signed(Method::DELETE, index, client);
+        *stolen.uri_mut() = path.parse().unwrap();
+        let res = send(&ctx, stolen).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = send(&ctx, signed(Method::DELETE, &path, client)).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let status = session_status(&ctx, id, nonce).await;
+        assert!(requester.verify(&status).unwrap());
+        assert!(!status.confirms(id, nonce));
+        let res = send(&ctx, signed(Method::DELETE, &path, operator)).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn rate_limited() {
+        let ctx = context(Vec::new());
+        let requester = requester("client2.privkey.pem");
+        let path = "/api/v1/authorizations";
+        for _ in 0..2 {
+            let request = requester.make("Client 2", "uname -a").unwrap();
+            let res = send(&ctx, post(path, &request)).await;
+            assert_eq!(res.status(), StatusCode::CREATED);
+        }
+        let request = requester.make("Client 2", "uname -a").unwrap();
+        let res = send(&ctx, post(path, &request)).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(res.headers().contains_key(header::RETRY_AFTER));
+        assert_eq!(ctx.database.lock().await.len(), 2);
+    }
+}
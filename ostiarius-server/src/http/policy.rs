@@ -0,0 +1,33 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::http::{
+    problem::ApiError,
+    wire::{Accept, Wire},
+    ApiContext,
+};
+use axum::{extract::Extension, response::IntoResponse, routing::post, Router};
+use ostiarius_core::ExplainRequest;
+
+async fn policy_explain(
+    accept: Accept,
+    Wire(request): Wire<ExplainRequest>,
+    Extension(ctx): Extension<ApiContext>,
+) -> std::result::Result<impl IntoResponse, ApiError> {
+    let explanation = ctx.checker().await.explain_request(&request)?;
+    tracing::info!(
+        "Operator '{}' got explanation for command '{}' of client '{}': {}",
+        &request.operator,
+        &request.command,
+        &request.name,
+        explanation.verdict
+    );
+    Ok(accept.encode(explanation))
+}
+
+pub fn router() -> Router {
+    Router::new().route("/api/v1/policy/explain", post(policy_explain))
+}
@@ -0,0 +1,59 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use ostiarius_core::{
+    problem::{Problem, Reason, PROBLEM_JSON},
+    Error,
+};
+
+/// Rejection of a request, answered with problem details.
+#[derive(Debug)]
+pub struct ApiError(pub Problem);
+
+impl From<Reason> for ApiError {
+    fn from(reason: Reason) -> Self {
+        ApiError(Problem::new(reason))
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        let problem = Problem::from(&error);
+        match problem.reason {
+            Reason::InternalError => tracing::error!("Failed to process request: {}", error),
+            _ => tracing::info!("Denied request: {}", error),
+        }
+        ApiError(problem)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status =
+            StatusCode::from_u16(self.0.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = match serde_json::to_vec(&self.0) {
+            Ok(data) => (
+                status,
+                [(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON))],
+                data,
+            )
+                .into_response(),
+            Err(_) => status.into_response(),
+        };
+        if let Some(retry_at) = self.0.retry_at {
+            let delay = ((retry_at - chrono::Utc::now()).num_milliseconds() + 999) / 1000;
+            let delay = delay.max(1);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(delay));
+        }
+        response
+    }
+}
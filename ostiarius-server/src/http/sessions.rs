@@ -0,0 +1,125 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::http::{
+    credential::Signer,
+    problem::ApiError,
+    source::Source,
+    wire::{Accept, Wire},
+    ApiContext,
+};
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use ostiarius_core::SessionRequest;
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+struct Nonce {
+    pub nonce: Uuid,
+}
+
+/// List the active sessions, for operators only.
+async fn sessions_index(
+    accept: Accept,
+    signer: Signer,
+    Extension(ctx): Extension<ApiContext>,
+) -> std::result::Result<impl IntoResponse, ApiError> {
+    signer.operator(&*ctx.checker().await)?;
+    let mut sessions = ctx.sessions.lock().await;
+    let now = chrono::offset::Utc::now();
+    sessions.retain(|_, session| !session.is_expired(now));
+    let sessions = sessions.values().cloned().collect::<Vec<_>>();
+    Ok(accept.encode(sessions))
+}
+
+/// Tell whether a session is still active, without revealing anything else.
+/// The status is signed and bound to the nonce of the client, so that it can
+/// not be forged nor replayed.
+async fn sessions_get(
+    accept: Accept,
+    Path(id): Path<Uuid>,
+    Query(Nonce { nonce }): Query<Nonce>,
+    Extension(ctx): Extension<ApiContext>,
+) -> std::result::Result<impl IntoResponse, ApiError> {
+    let checker = ctx.checker().await;
+    let active = {
+        let sessions = ctx.sessions.lock().await;
+        let now = chrono::offset::Utc::now();
+        matches!(sessions.get(&id), Some(session) if !session.is_expired(now))
+    };
+    let status = checker.session_status(id, nonce, active)?;
+    Ok(accept.encode(status))
+}
+
+async fn sessions_create(
+    accept: Accept,
+    Source(source): Source,
+    Wire(request): Wire<SessionRequest>,
+    Extension(ctx): Extension<ApiContext>,
+) -> std::result::Result<impl IntoResponse, ApiError> {
+    let session = ctx.checker().await.open_session(&request, source)?;
+    let mut sessions = ctx.sessions.lock().await;
+    tracing::info!(
+        "Opened session {} for client '{}' until {}",
+        &session.id,
+        &session.name,
+        &session.expires
+    );
+    sessions.insert(session.id, session.clone());
+    Ok((StatusCode::CREATED, accept.encode(session)))
+}
+
+/// Revoke a session, for operators or the client owning it.
+async fn sessions_delete(
+    Path(id): Path<Uuid>,
+    signer: Signer,
+    Extension(ctx): Extension<ApiContext>,
+) -> std::result::Result<StatusCode, ApiError> {
+    let checker = ctx.checker().await;
+    let mut sessions = ctx.sessions.lock().await;
+    let owner = sessions.get(&id).map(|session| session.name.clone());
+    let revoker = match signer.operator(&checker) {
+        Ok(operator) => format!("operator '{}'", operator),
+        Err(_) => {
+            let name = owner
+                .as_deref()
+                .or_else(|| signer.name())
+                .unwrap_or_default();
+            signer.client(&checker, name)?;
+            format!("client '{}'", name)
+        }
+    };
+    match sessions.remove(&id) {
+        Some(session) => {
+            tracing::info!(
+                "Revoked session {} of client '{}' by {}",
+                &session.id,
+                &session.name,
+                revoker
+            );
+            Ok(StatusCode::NO_CONTENT)
+        }
+        None => Ok(StatusCode::NOT_FOUND),
+    }
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route(
+            "/api/v1/sessions",
+            get(sessions_index).post(sessions_create),
+        )
+        .route(
+            "/api/v1/sessions/:id",
+            get(sessions_get).delete(sessions_delete),
+        )
+}
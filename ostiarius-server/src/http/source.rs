@@ -0,0 +1,116 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::http::ApiContext;
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequest, RequestParts},
+};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Address of the client which sent the request, if known.
+///
+/// When the peer is a trusted proxy, the address is taken from the
+/// `X-Forwarded-For` header: it is the last address which is not itself a
+/// trusted proxy.
+#[derive(Debug, Clone, Copy)]
+pub struct Source(pub Option<IpAddr>);
+
+fn is_trusted(proxies: &[IpNet], addr: &IpAddr) -> bool {
+    proxies.iter().any(|net| net.contains(addr))
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Source {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let peer = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let proxies = req
+            .extensions()
+            .get::<ApiContext>()
+            .map(|ctx| ctx.trusted_proxies.clone())
+            .unwrap_or_default();
+        let mut source = peer;
+        if let Some(peer) = peer.filter(|peer| is_trusted(&proxies, peer)) {
+            let forwarded = req
+                .headers()
+                .get_all(X_FORWARDED_FOR)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .map(|addr| addr.trim().parse::<IpAddr>().ok())
+                .collect::<Vec<_>>();
+            source = Some(peer);
+            for addr in forwarded.into_iter().rev() {
+                source = addr;
+                match addr {
+                    Some(addr) if is_trusted(&proxies, &addr) => continue,
+                    _ => break,
+                }
+            }
+        }
+        Ok(Source(source))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::context;
+    use axum::{body::Body, http::Request};
+
+    async fn source(peer: &str, forwarded: &[&str]) -> Option<IpAddr> {
+        let proxies = ["10.0.0.0/8", "192.168.1.1/32"]
+            .iter()
+            .map(|net| net.parse().unwrap())
+            .collect();
+        let peer = SocketAddr::new(peer.parse().unwrap(), 4000);
+        let mut builder = Request::builder()
+            .extension(ConnectInfo(peer))
+            .extension(context(proxies));
+        for value in forwarded {
+            builder = builder.header(X_FORWARDED_FOR, *value);
+        }
+        let mut req = RequestParts::new(builder.body(Body::empty()).unwrap());
+        let Source(source) = Source::from_request(&mut req).await.unwrap();
+        source
+    }
+
+    fn addr(s: &str) -> Option<IpAddr> {
+        s.parse().ok()
+    }
+
+    #[tokio::test]
+    async fn forwarded_for() {
+        // Untrusted peers can not choose their address.
+        assert_eq!(
+            source("203.0.113.7", &["1.2.3.4"]).await,
+            addr("203.0.113.7")
+        );
+        // Without header, the source is the proxy itself.
+        assert_eq!(source("10.0.0.1", &[]).await, addr("10.0.0.1"));
+        assert_eq!(source("10.0.0.1", &["1.2.3.4"]).await, addr("1.2.3.4"));
+        // Addresses added by the client before the trusted proxies are ignored.
+        assert_eq!(
+            source("10.0.0.1", &["6.6.6.6, 1.2.3.4", "192.168.1.1"]).await,
+            addr("1.2.3.4")
+        );
+        assert_eq!(
+            source("10.0.0.1", &["1.2.3.4", "10.0.0.2"]).await,
+            addr("1.2.3.4")
+        );
+        // A malformed address makes the source unknown.
+        assert_eq!(source("10.0.0.1", &["1.2.3.4, bogus"]).await, None);
+        assert_eq!(source("::1", &[]).await, addr("::1"));
+    }
+}
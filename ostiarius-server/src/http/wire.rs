@@ -0,0 +1,93 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::{FromRequest, RequestParts},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use ostiarius_core::wire::Format;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Request body, decoded according to its `Content-Type` header.
+pub struct Wire<T>(pub T);
+
+#[async_trait]
+impl<T, B> FromRequest<B> for Wire<T>
+where
+    T: DeserializeOwned,
+    B: HttpBody + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let format = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Format::from_content_type)
+            .ok_or(StatusCode::UNSUPPORTED_MEDIA_TYPE)?;
+        let bytes = Bytes::from_request(req)
+            .await
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+        let value = format
+            .decode(&bytes)
+            .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+        Ok(Wire(value))
+    }
+}
+
+/// Format of the response, selected from the `Accept` header of the request.
+#[derive(Debug, Clone, Copy)]
+pub struct Accept(pub Format);
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Accept {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let format = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .map(Format::from_accept)
+            .unwrap_or_default();
+        Ok(Accept(format))
+    }
+}
+
+impl Accept {
+    pub fn encode<T: Serialize>(&self, value: T) -> Encoded<T> {
+        Encoded(self.0, value)
+    }
+}
+
+/// Response body, encoded in the format selected by the client.
+pub struct Encoded<T>(pub Format, pub T);
+
+impl<T: Serialize> IntoResponse for Encoded<T> {
+    fn into_response(self) -> Response {
+        match self.0.encode(&self.1) {
+            Ok(data) => (
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(self.0.mime()),
+                )],
+                data,
+            )
+                .into_response(),
+            Err(e) => {
+                tracing::error!("Failed to encode response: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
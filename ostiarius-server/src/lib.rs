@@ -6,4 +6,8 @@
 
 pub mod config;
 pub mod http;
+pub mod logging;
 pub mod models;
+pub mod reload;
+#[cfg(unix)]
+pub mod serial;
@@ -0,0 +1,36 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use ostiarius_core::Authorization;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+pub fn init() {
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new(
+            std::env::var("RUST_LOG").unwrap_or_else(|_| {
+                "ostiarius_server=info,ostiarius_core=info,tower_http=info".into()
+            }),
+        ))
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+}
+
+/// Log the grant of an authorization, with the digest of its script if any.
+pub fn granted(authorization: &Authorization) {
+    match &authorization.exec.script {
+        Some(script) => tracing::info!(
+            "Granted authorization {} to client '{}' for script {}",
+            &authorization.id,
+            &authorization.name,
+            &script.sha256
+        ),
+        None => tracing::info!(
+            "Granted authorization {} to client '{}'",
+            &authorization.id,
+            &authorization.name
+        ),
+    }
+}
@@ -5,12 +5,22 @@
 //
 
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use gumdrop::Options;
+use ipnet::IpNet;
 use ostiarius_core::{
-    crypto::password::PasswordProvider, utils::insert_password, Authorizations, Checker,
+    crypto::password::PasswordProvider,
+    lint::{lint_path, Severity},
+    utils::insert_password,
+    Checker, FileProvider, OfflineAuthorization, OfflineRequest, PolicyFormat, PolicyProvider,
+    Verdict,
 };
-use ostiarius_server::{config::Config, http, models};
+#[cfg(unix)]
+use ostiarius_server::serial;
+use ostiarius_server::{config::Config, http, models, reload};
 use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
 
 #[derive(Debug, Options)]
 pub struct ServerOptions {
@@ -22,8 +32,26 @@ pub struct ServerOptions {
     pub address: Option<String>,
     #[options(help = "Port to use")]
     pub port: Option<u16>,
-    #[options(help = "Path to authorizations file", meta = "FILE")]
+    #[options(help = "Serial device to listen on, instead of HTTP", meta = "DEVICE")]
+    pub serial: Option<String>,
+    #[options(help = "Baud rate of serial device (default: 115200)", meta = "RATE")]
+    pub baud_rate: Option<u32>,
+    #[options(
+        help = "Network of a proxy trusted to set X-Forwarded-For (repeatable)",
+        meta = "NETWORK",
+        no_short
+    )]
+    pub trusted_proxy: Vec<String>,
+    #[options(help = "Path to authorizations file or directory", meta = "PATH")]
     pub authorizations: Option<String>,
+    #[options(
+        help = "Format of authorizations: toml, yaml or json (default: from extension)",
+        meta = "FORMAT",
+        no_short
+    )]
+    pub policy_format: Option<PolicyFormat>,
+    #[options(help = "Reload authorizations when their file changes", no_short)]
+    pub watch: bool,
     #[options(help = "URI of server private key", meta = "URI")]
     priv_key: Option<String>,
     #[options(
@@ -33,6 +61,153 @@ pub struct ServerOptions {
         short = "S"
     )]
     password_provider: Option<String>,
+    #[options(command)]
+    command: Option<ServerCommand>,
+}
+
+#[derive(Debug, Options)]
+enum ServerCommand {
+    #[options(help = "Check an authorization request read from a file, without network")]
+    Offline(OfflineOptions),
+    #[options(help = "Check the authorizations file and exit, without private key")]
+    CheckConfig(CheckConfigOptions),
+    #[options(help = "Explain the decision about a command and exit, without private key")]
+    Explain(ExplainOptions),
+}
+
+#[derive(Debug, Options)]
+struct OfflineOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(help = "Path to output file (default: standard output)", meta = "FILE")]
+    out: Option<String>,
+    #[options(free)]
+    file: String,
+}
+
+#[derive(Debug, Options)]
+struct CheckConfigOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(
+        free,
+        help = "Path to authorizations file or directory (default: --authorizations)"
+    )]
+    file: Option<String>,
+}
+
+#[derive(Debug, Options)]
+struct ExplainOptions {
+    #[options(help = "Print this help message and exit")]
+    help: bool,
+    #[options(help = "Name of the client", meta = "NAME")]
+    client: String,
+    #[options(help = "Address the request comes from", meta = "ADDRESS")]
+    source: Option<IpAddr>,
+    #[options(
+        help = "Time of the request, in RFC 3339 format (default: now)",
+        meta = "TIME"
+    )]
+    at: Option<DateTime<Utc>>,
+    #[options(free)]
+    command: Vec<String>,
+}
+
+fn explain(provider: &FileProvider, options: &ExplainOptions) -> anyhow::Result<()> {
+    if options.command.is_empty() {
+        anyhow::bail!("missing command to explain");
+    }
+    let authorizations = provider.load().context("failed to load authorizations")?;
+    let explanation = authorizations.explain(
+        &options.client,
+        &options.command.join(" "),
+        options.source,
+        options.at.unwrap_or_else(Utc::now),
+    );
+    println!("{}", explanation);
+    if explanation.verdict == Verdict::Denied {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn check_config(path: &str, format: PolicyFormat) -> anyhow::Result<()> {
+    let diagnostics = lint_path(path, format).context("failed to read authorizations")?;
+    for diagnostic in &diagnostics {
+        let file = diagnostic
+            .file
+            .as_ref()
+            .map_or_else(|| path.to_string(), |file| file.display().to_string());
+        match diagnostic.line {
+            Some(line) => eprintln!(
+                "{}:{}: {}: {}",
+                file, line, diagnostic.severity, diagnostic.message
+            ),
+            None => eprintln!("{}: {}: {}", file, diagnostic.severity, diagnostic.message),
+        }
+    }
+    let count = |severity| {
+        diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count()
+    };
+    let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+    eprintln!("{}: {} error(s), {} warning(s)", path, errors, warnings);
+    if errors > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn offline(checker: &Checker, options: &OfflineOptions) -> anyhow::Result<()> {
+    let armored = std::fs::read_to_string(&options.file).context("failed to read request")?;
+    let request = OfflineRequest::from_armor(&armored).context("failed to decode request")?;
+    let authorization = checker
+        .check(&request.request, None)
+        .context("failed to check request")?;
+    eprintln!(
+        "Granted authorization {} to client '{}' for command '{}'",
+        &authorization.id, &authorization.name, &authorization.command
+    );
+    let authorization = OfflineAuthorization {
+        request_id: request.id,
+        authorization,
+    };
+    let armored = authorization
+        .to_armor()
+        .context("failed to encode authorization")?;
+    match &options.out {
+        Some(path) if path != "-" => {
+            std::fs::write(path, armored).context("failed to write authorization")?
+        }
+        _ => print!("{}", armored),
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn serve_serial(
+    checker: models::SharedChecker,
+    limits: models::Limits,
+    device: String,
+    baud_rate: Option<u32>,
+) -> anyhow::Result<()> {
+    let address = ostiarius_core::serial::SerialAddress {
+        path: device.into(),
+        baud_rate: baud_rate.unwrap_or(ostiarius_core::serial::DEFAULT_BAUD_RATE),
+    };
+    tokio::task::spawn_blocking(move || serial::serve(checker, limits, address)).await?
+}
+
+#[cfg(not(unix))]
+async fn serve_serial(
+    _checker: models::SharedChecker,
+    _limits: models::Limits,
+    _device: String,
+    _baud_rate: Option<u32>,
+) -> anyhow::Result<()> {
+    anyhow::bail!("serial transport is not supported on this platform")
 }
 
 #[tokio::main]
@@ -48,6 +223,32 @@ async fn main() -> anyhow::Result<()> {
         .parse::<IpAddr>()
         .context("Failed to parse IP address")?;
     let port = options.port.unwrap_or(3000);
+    let authorizations = options
+        .authorizations
+        .unwrap_or_else(|| "authorizations.toml".to_string());
+    let format = |path: &str| {
+        options
+            .policy_format
+            .or_else(|| PolicyFormat::from_path(path))
+            .unwrap_or_default()
+    };
+    if let Some(ServerCommand::CheckConfig(check_options)) = &options.command {
+        let path = check_options.file.as_ref().unwrap_or(&authorizations);
+        return check_config(path, format(path));
+    }
+    let provider = FileProvider::new(&authorizations, format(&authorizations));
+    if let Some(ServerCommand::Explain(explain_options)) = &options.command {
+        return explain(&provider, explain_options);
+    }
+    let trusted_proxies = options
+        .trusted_proxy
+        .iter()
+        .map(|net| match net.parse::<IpNet>() {
+            Ok(net) => Ok(net),
+            Err(_) => net.parse::<IpAddr>().map(IpNet::from),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to parse trusted proxy network")?;
     let mut path = std::env::current_dir().context("failed to get current directory")?;
     path.push("server.privkey.pem");
     let priv_key = options
@@ -61,17 +262,25 @@ async fn main() -> anyhow::Result<()> {
         .provide()
         .context("failed to get password")?;
     let priv_key = insert_password(&password, &priv_key)?;
-    let authorizations = options
-        .authorizations
-        .unwrap_or_else(|| "authorizations.toml".to_string());
-    let authorizations =
-        Authorizations::from_file(authorizations).context("failed to load authorizations")?;
-    let checker = Checker::new(&priv_key, authorizations).context("failed to create checker")?;
+    let checker = Checker::with_provider(&priv_key, Arc::new(provider.clone()))
+        .context("failed to create checker")?;
+    if let Some(ServerCommand::Offline(offline_options)) = &options.command {
+        return offline(&checker, offline_options);
+    }
+    let checker = Arc::new(RwLock::new(Arc::new(checker)));
+    reload::spawn(checker.clone(), provider, options.watch)?;
+    let limits = models::Limits::default();
+    if let Some(device) = options.serial {
+        return serve_serial(checker, limits, device, options.baud_rate).await;
+    }
     let config = Config {
         address,
         port,
         checker,
+        trusted_proxies,
     };
     let db = models::Database::default();
-    http::serve(config, db).await
+    let sessions = models::Sessions::default();
+    let approvals = models::Approvals::default();
+    http::serve(config, db, sessions, approvals, limits).await
 }
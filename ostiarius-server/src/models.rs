@@ -4,10 +4,43 @@
 // SPDX-License-Identifier: MIT
 //
 
-use ostiarius_core::Authorization;
+use chrono::{DateTime, Duration, Utc};
+use ostiarius_core::{limits::Limiter, Authorization, Checker, PendingAuthorization, Session};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, RwLock};
 use uuid::Uuid;
 
 pub type Database = Arc<Mutex<HashMap<Uuid, Authorization>>>;
+pub type Sessions = Arc<Mutex<HashMap<Uuid, Session>>>;
+pub type Limits = Arc<Mutex<Limiter>>;
+pub type SharedChecker = Arc<RwLock<Arc<Checker>>>;
+
+#[derive(Debug, Clone)]
+pub enum Pending {
+    Waiting(PendingAuthorization),
+    Denied(PendingAuthorization),
+}
+
+/// Time after which a request waiting for approval, or denied, is forgotten,
+/// in seconds.
+pub const PENDING_LIFETIME: i64 = 3600;
+
+impl Pending {
+    pub fn request(&self) -> &PendingAuthorization {
+        match self {
+            Pending::Waiting(pending) | Pending::Denied(pending) => pending,
+        }
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now - self.request().timestamp > Duration::seconds(PENDING_LIFETIME)
+    }
+}
+
+pub type Approvals = Arc<Mutex<HashMap<Uuid, Pending>>>;
+
+/// Forget the requests waiting for approval for too long.
+pub fn prune_approvals(approvals: &mut HashMap<Uuid, Pending>, now: DateTime<Utc>) {
+    approvals.retain(|_, pending| !pending.is_expired(now));
+}
@@ -0,0 +1,119 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+//! Reload of the authorizations while the server is running.
+//!
+//! The authorizations are reloaded on SIGHUP and, if requested, whenever their
+//! file, or one of the fragments of their directory, changes. They replace the
+//! current ones only if they are valid.
+
+use crate::models::SharedChecker;
+use anyhow::Context;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use ostiarius_core::{FileProvider, PolicyFormat};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+/// Delay to let the file settle after a change, before reloading it.
+const SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+/// Load the authorizations again from the provider of the checker, here the
+/// file at `path`, and swap them in. On failure, the current authorizations
+/// stay active.
+pub async fn reload(checker: &SharedChecker, path: &Path) -> bool {
+    let current = checker.read().await.clone();
+    match current.reload() {
+        Ok(reloaded) => {
+            *checker.write().await = Arc::new(reloaded);
+            tracing::info!("Reloaded authorizations from {}", path.display());
+            true
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to reload authorizations from {}, keeping current ones: {}",
+                path.display(),
+                e
+            );
+            false
+        }
+    }
+}
+
+fn watch_file(
+    provider: &FileProvider,
+    sender: UnboundedSender<()>,
+) -> anyhow::Result<RecommendedWatcher> {
+    // Watch the directory of a single file, as editors often replace the file
+    // instead of writing it in place. For a directory of fragments, watch all
+    // its files in the format of the policy.
+    let (path, format) = (provider.path(), provider.format());
+    let (dir, name) = if path.is_dir() {
+        (path.to_path_buf(), None)
+    } else {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        (dir.to_path_buf(), path.file_name().map(ToOwned::to_owned))
+    };
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => return tracing::warn!("Failed to watch authorizations: {}", e),
+        };
+        if event.kind.is_access() || event.kind.is_other() {
+            return;
+        }
+        let relevant = |p: &PathBuf| match &name {
+            Some(name) => p.file_name() == Some(name.as_os_str()),
+            None => PolicyFormat::from_path(p) == Some(format),
+        };
+        if event.paths.iter().any(relevant) {
+            let _ = sender.send(());
+        }
+    })
+    .context("failed to create file watcher")?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("failed to watch {}", dir.display()))?;
+    Ok(watcher)
+}
+
+/// Reload the authorizations from the file of `provider` on SIGHUP and, if
+/// `watch` is set, when the file or its fragments change.
+pub fn spawn(checker: SharedChecker, provider: FileProvider, watch: bool) -> anyhow::Result<()> {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hangup = signal(SignalKind::hangup()).context("failed to handle SIGHUP")?;
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                tracing::info!("Received SIGHUP");
+                if sender.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    let watcher = if watch {
+        Some(watch_file(&provider, sender)?)
+    } else {
+        None
+    };
+    tokio::spawn(async move {
+        let _watcher = watcher;
+        while receiver.recv().await.is_some() {
+            tokio::time::sleep(SETTLE_DELAY).await;
+            while receiver.try_recv().is_ok() {}
+            reload(&checker, provider.path()).await;
+        }
+    });
+    Ok(())
+}
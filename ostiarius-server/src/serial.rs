@@ -0,0 +1,56 @@
+//
+// Copyright (C) 2022 Eric Le Bihan <eric.le.bihan.dev@free.fr>
+//
+// SPDX-License-Identifier: MIT
+//
+
+use crate::logging;
+use crate::models::{Limits, SharedChecker};
+use anyhow::Context;
+use ostiarius_core::{
+    problem::Reason,
+    serial::{self, SerialAddress},
+    Error,
+};
+use std::time::Duration;
+
+/// Maximum time between two bytes of a frame, before it is considered
+/// incomplete.
+const FRAME_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Answer authorization requests received over a serial line, with the same
+/// policy and rate limits as the HTTP transport.
+///
+/// A serial client can not wait for an approval, so requests which need one
+/// are denied.
+pub fn serve(checker: SharedChecker, limits: Limits, address: SerialAddress) -> anyhow::Result<()> {
+    logging::init();
+    let mut port = serial::open(&address.path, address.baud_rate, Some(FRAME_TIMEOUT))
+        .with_context(|| format!("failed to open {}", address.path.display()))?;
+    tracing::info!("Listening on {}", address.path.display());
+    loop {
+        let result = serial::answer(&mut port, |request| {
+            let checker = checker.blocking_read().clone();
+            checker.throttle(
+                &mut limits.blocking_lock(),
+                &request.name,
+                None,
+                &request.command,
+                request.params.as_ref(),
+            )?;
+            checker.check(request, None)
+        });
+        match result {
+            Ok(authorization) => logging::granted(&authorization),
+            Err(Error::Io(e)) => return Err(e).context("failed to communicate over serial line"),
+            Err(e @ Error::InvalidFrame(_)) => tracing::warn!("Received invalid frame: {}", e),
+            Err(e)
+                if matches!(e, Error::ApprovalRequired)
+                    || Reason::from(&e) != Reason::InternalError =>
+            {
+                tracing::info!("Denied authorization: {}", e)
+            }
+            Err(e) => tracing::warn!("Failed to answer request: {}", e),
+        }
+    }
+}